@@ -2,22 +2,38 @@
 set -e
 
 REPO="filiphric/portmap"
-INSTALL_DIR="/usr/local/bin"
+# Set INSTALL_DIR to a directory you own (e.g. ~/.local/bin) so that
+# `portmap update` and the U key can replace the binary in place
+INSTALL_DIR="${INSTALL_DIR:-/usr/local/bin}"
 
 # Detect architecture
 ARCH=$(uname -m)
 case "$ARCH" in
-  arm64|aarch64) TARGET="aarch64-apple-darwin" ;;
-  x86_64)        TARGET="x86_64-apple-darwin" ;;
+  arm64|aarch64) ARCH="aarch64" ;;
+  x86_64)        ARCH="x86_64" ;;
   *)
     echo "Unsupported architecture: $ARCH"
     exit 1
     ;;
 esac
 
+# Detect OS; on Linux the statically linked musl build runs everywhere
 OS=$(uname -s)
-if [ "$OS" != "Darwin" ]; then
-  echo "Unsupported OS: $OS (only macOS is supported)"
+case "$OS" in
+  Darwin) TARGET="$ARCH-apple-darwin" ;;
+  Linux)  TARGET="$ARCH-unknown-linux-musl" ;;
+  *)
+    echo "Unsupported OS: $OS (only macOS and Linux are supported)"
+    exit 1
+    ;;
+esac
+
+if command -v sha256sum >/dev/null 2>&1; then
+  SHA256="sha256sum"
+elif command -v shasum >/dev/null 2>&1; then
+  SHA256="shasum -a 256"
+else
+  echo "Neither sha256sum nor shasum found; cannot verify the download"
   exit 1
 fi
 
@@ -34,9 +50,20 @@ URL="https://github.com/$REPO/releases/download/$LATEST/portmap-$TARGET.tar.gz"
 echo "Downloading portmap $LATEST for $TARGET..."
 TMPDIR=$(mktemp -d)
 curl -fsSL "$URL" -o "$TMPDIR/portmap.tar.gz"
+curl -fsSL "$URL.sha256" -o "$TMPDIR/portmap.tar.gz.sha256"
+
+EXPECTED=$(cut -d ' ' -f 1 < "$TMPDIR/portmap.tar.gz.sha256")
+ACTUAL=$($SHA256 "$TMPDIR/portmap.tar.gz" | cut -d ' ' -f 1)
+if [ -z "$EXPECTED" ] || [ "$EXPECTED" != "$ACTUAL" ]; then
+  echo "Checksum mismatch for portmap-$TARGET.tar.gz; not installing"
+  rm -rf "$TMPDIR"
+  exit 1
+fi
+
 tar xzf "$TMPDIR/portmap.tar.gz" -C "$TMPDIR"
 
 echo "Installing to $INSTALL_DIR (may require sudo)..."
+mkdir -p "$INSTALL_DIR" 2>/dev/null || true
 if [ -w "$INSTALL_DIR" ]; then
   mv "$TMPDIR/portmap" "$INSTALL_DIR/portmap"
 else
@@ -1,3 +1,11 @@
+use crate::hosts::watcher::HostsDrift;
+use crate::privilege::RunMode;
+use crate::probe::HealthCheck;
+use crate::proxy::server::ProxyStatus;
+use crate::update::release::Release;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
 /// A single domain → port mapping.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Mapping {
@@ -7,14 +15,73 @@ pub struct Mapping {
     pub port: u16,
     /// Whether the port is reachable
     pub status: MappingStatus,
+    /// Optional HTTP health check; without one only a TCP connect is tried
+    pub health: Option<HealthCheck>,
+    /// Response time of the last successful probe
+    pub latency: Option<Duration>,
+    /// Disabled mappings keep their config but are left out of name
+    /// resolution, and the proxy answers with the disabled page
+    pub enabled: bool,
+    /// Free-form labels for finding the mapping with the filter
+    pub tags: Vec<String>,
+}
+
+impl Mapping {
+    pub fn new(domain: String, port: u16) -> Self {
+        Self {
+            domain,
+            port,
+            status: MappingStatus::Unknown,
+            health: None,
+            latency: None,
+            enabled: true,
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Split tag input on spaces and commas, lowercased and without repeats.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in input.split([' ', ',']).filter(|t| !t.is_empty()) {
+        let tag = tag.to_lowercase();
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Find the mapping that serves `host`: an exact match, or else the
+/// closest parent domain, so `*.my-project.localhost` reaches the same port.
+pub fn find_mapping<'a>(mappings: &'a [Mapping], host: &str) -> Option<&'a Mapping> {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    mappings.iter().find(|m| m.domain == host).or_else(|| {
+        mappings
+            .iter()
+            .filter(|m| {
+                host.strip_suffix(m.domain.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+            .max_by_key(|m| m.domain.len())
+    })
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum MappingStatus {
+    /// Port accepts connections (no health check configured)
     Active,
     PortUnreachable,
     /// Not yet checked
     Unknown,
+    /// Health check answered with an expected status
+    Healthy,
+    /// Health check answered with this unexpected status
+    Unhealthy(u16),
+    /// Health check hasn't passed yet and the server isn't up
+    Starting,
+    /// No answer within the timeout
+    Timeout,
 }
 
 impl std::fmt::Display for MappingStatus {
@@ -23,10 +90,130 @@ impl std::fmt::Display for MappingStatus {
             MappingStatus::Active => write!(f, "Active"),
             MappingStatus::PortUnreachable => write!(f, "Port Unreachable"),
             MappingStatus::Unknown => write!(f, "Unknown"),
+            MappingStatus::Healthy => write!(f, "Healthy"),
+            MappingStatus::Unhealthy(code) => write!(f, "Unhealthy ({})", code),
+            MappingStatus::Starting => write!(f, "Starting"),
+            MappingStatus::Timeout => write!(f, "Timeout"),
         }
     }
 }
 
+impl MappingStatus {
+    /// Stable lowercase name, for scripts (e.g. `PORTMAP_STATUS`).
+    pub fn key(&self) -> &'static str {
+        match self {
+            MappingStatus::Active => "active",
+            MappingStatus::PortUnreachable => "port-unreachable",
+            MappingStatus::Unknown => "unknown",
+            MappingStatus::Healthy => "healthy",
+            MappingStatus::Unhealthy(_) => "unhealthy",
+            MappingStatus::Starting => "starting",
+            MappingStatus::Timeout => "timeout",
+        }
+    }
+
+    /// Whether the upstream is serving (with its health check passing, if any).
+    pub fn is_up(&self) -> bool {
+        matches!(self, MappingStatus::Active | MappingStatus::Healthy)
+    }
+
+    /// Sort rank: problems first, working mappings last.
+    fn rank(&self) -> u8 {
+        match self {
+            MappingStatus::PortUnreachable
+            | MappingStatus::Unhealthy(_)
+            | MappingStatus::Timeout => 0,
+            MappingStatus::Starting => 1,
+            MappingStatus::Unknown => 2,
+            MappingStatus::Active | MappingStatus::Healthy => 3,
+        }
+    }
+}
+
+/// Order of the rows in the mappings table.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortOrder {
+    /// Oldest first, as added
+    #[default]
+    Added,
+    Domain,
+    Port,
+    /// Problems first; disabled mappings last
+    Status,
+    /// Newest first
+    Recent,
+}
+
+impl SortOrder {
+    pub fn next(self) -> Self {
+        match self {
+            SortOrder::Added => SortOrder::Domain,
+            SortOrder::Domain => SortOrder::Port,
+            SortOrder::Port => SortOrder::Status,
+            SortOrder::Status => SortOrder::Recent,
+            SortOrder::Recent => SortOrder::Added,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortOrder::Added => "added",
+            SortOrder::Domain => "domain",
+            SortOrder::Port => "port",
+            SortOrder::Status => "status",
+            SortOrder::Recent => "recently added",
+        }
+    }
+}
+
+/// Whether `text` contains the characters of `pattern` in order
+/// (case-insensitive), e.g. `apl` matches `api.localhost`.
+fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(|c| c.to_lowercase());
+    pattern
+        .chars()
+        .flat_map(|c| c.to_lowercase())
+        .all(|p| text.any(|t| t == p))
+}
+
+/// Whether `mapping` passes the table filter: every whitespace-separated
+/// word must fuzzy-match its domain, port, status or one of its tags.
+pub fn filter_matches(filter: &str, mapping: &Mapping) -> bool {
+    let port = mapping.port.to_string();
+    let status = if mapping.enabled {
+        mapping.status.key()
+    } else {
+        "disabled"
+    };
+    filter.split_whitespace().all(|word| {
+        [mapping.domain.as_str(), port.as_str(), status]
+            .into_iter()
+            .chain(mapping.tags.iter().map(String::as_str))
+            .any(|field| fuzzy_match(word, field))
+    })
+}
+
+/// The mappings shown in the table, filtered and sorted.
+pub fn visible_mappings<'a>(
+    mappings: &'a [Mapping],
+    filter: &str,
+    sort: SortOrder,
+) -> Vec<&'a Mapping> {
+    let mut rows: Vec<&Mapping> = mappings
+        .iter()
+        .filter(|m| filter_matches(filter, m))
+        .collect();
+    // Stable sorts, so ties stay in the order they were added
+    match sort {
+        SortOrder::Added => {}
+        SortOrder::Domain => rows.sort_by(|a, b| a.domain.cmp(&b.domain)),
+        SortOrder::Port => rows.sort_by_key(|m| m.port),
+        SortOrder::Status => rows.sort_by_key(|m| (!m.enabled, m.status.rank())),
+        SortOrder::Recent => rows.reverse(),
+    }
+    rows
+}
+
 /// The current mode of the TUI.
 #[derive(Debug, Clone, PartialEq)]
 pub enum InputMode {
@@ -34,40 +221,243 @@ pub enum InputMode {
     Normal,
     /// Adding a new mapping (popup visible)
     Adding,
+    /// Typing in the table filter
+    Filtering,
+    /// Editing the mapping for this domain (popup visible, pre-filled)
+    Editing(String),
+    /// Waiting for y/n before deleting the mapping for this domain
+    ConfirmDelete(String),
 }
 
-/// Which field is focused in the add-mapping popup.
+/// Which field is focused in the add/edit popup.
 #[derive(Debug, Clone, PartialEq)]
 pub enum PopupField {
     Domain,
     Port,
+    Health,
+    Tags,
+}
+
+/// Whether an input problem blocks saving.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem with what's typed in the popup, shown under its field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputIssue {
+    pub field: PopupField,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Facts about this session shown in the status bar.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub mode: RunMode,
+    /// Addresses the proxy is actually bound to
+    pub listen: Vec<std::net::SocketAddr>,
+    /// Whether `U` can install updates (the binary's directory is writable)
+    pub self_update: bool,
 }
 
 /// State for the TUI (not shared with the proxy — the proxy uses the watch channel).
 pub struct TuiState {
-    /// Currently selected row index
-    pub selected: usize,
+    /// How this session is running
+    pub session: SessionInfo,
+    /// Domain of the selected mapping, so the selection follows it
+    /// through re-sorts and filtering
+    pub selected: Option<String>,
+    /// Row the selection was last on, for when its mapping goes away
+    pub selected_row: usize,
+    /// Table filter (see [`filter_matches`])
+    pub filter: String,
+    pub sort: SortOrder,
+    /// First table row on screen
+    pub table_offset: usize,
+    /// Last click on a table row, to detect double-clicks
+    pub last_click: Option<(Instant, String)>,
     /// Current input mode
     pub mode: InputMode,
+    /// Help overlay for the current mode is open
+    pub show_help: bool,
     /// Domain input buffer (without .localhost suffix)
     pub domain_input: String,
     /// Port input buffer
     pub port_input: String,
+    /// Health check input buffer (`/path [range] [timeout]`, may be empty)
+    pub health_input: String,
+    /// Tags input buffer (space- or comma-separated, may be empty)
+    pub tags_input: String,
     /// Currently focused popup field
     pub popup_field: PopupField,
+    /// Problems with the popup input, updated as it's typed
+    pub input_issues: Vec<InputIssue>,
+    /// Last check of whether anything listens on a port: (port, listening, when)
+    pub port_listening: Option<(u16, bool, Instant)>,
     /// Status message shown in the status bar
     pub status_message: Option<String>,
+    /// Latest external edit to the hosts file that had to be repaired
+    pub hosts_drift: Option<HostsDrift>,
+    /// Quit requested; waiting for the proxy to drain
+    pub draining: bool,
+    /// Latest state published by the proxy
+    pub proxy: ProxyStatus,
+    /// Newer release found by the background update check
+    pub update: Option<Release>,
+    /// When each mapping's status last changed, by domain
+    pub status_changed: HashMap<String, Instant>,
 }
 
 impl TuiState {
-    pub fn new() -> Self {
+    pub fn new(session: SessionInfo) -> Self {
         Self {
-            selected: 0,
+            session,
+            selected: None,
+            selected_row: 0,
+            filter: String::new(),
+            sort: SortOrder::default(),
+            table_offset: 0,
+            last_click: None,
             mode: InputMode::Normal,
+            show_help: false,
             domain_input: String::new(),
             port_input: String::new(),
+            health_input: String::new(),
+            tags_input: String::new(),
             popup_field: PopupField::Domain,
+            input_issues: Vec::new(),
+            port_listening: None,
             status_message: None,
+            hosts_drift: None,
+            draining: false,
+            proxy: ProxyStatus::default(),
+            update: None,
+            status_changed: HashMap::new(),
+        }
+    }
+
+    /// The table rows for `mappings`, with the current filter and sort.
+    pub fn visible<'a>(&self, mappings: &'a [Mapping]) -> Vec<&'a Mapping> {
+        visible_mappings(mappings, &self.filter, self.sort)
+    }
+
+    /// The selected mapping and its index in `mappings`.
+    pub fn selected_mapping<'a>(&self, mappings: &'a [Mapping]) -> Option<(usize, &'a Mapping)> {
+        let domain = self.selected.as_deref()?;
+        mappings.iter().enumerate().find(|(_, m)| m.domain == domain)
+    }
+
+    /// Re-anchor the selection after the rows changed: it stays on the same
+    /// mapping if that is still shown, otherwise on the same row.
+    pub fn sync_selection(&mut self, rows: &[&Mapping]) {
+        if let Some(row) = rows
+            .iter()
+            .position(|m| Some(m.domain.as_str()) == self.selected.as_deref())
+        {
+            self.selected_row = row;
+            return;
+        }
+        self.selected_row = self.selected_row.min(rows.len().saturating_sub(1));
+        self.selected = rows.get(self.selected_row).map(|m| m.domain.clone());
+    }
+
+    /// Select the row at `row` (clamped to the table).
+    pub fn select_row(&mut self, rows: &[&Mapping], row: usize) {
+        if rows.is_empty() {
+            return;
         }
+        self.selected_row = row.min(rows.len() - 1);
+        self.selected = Some(rows[self.selected_row].domain.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(domain: &str, port: u16, status: MappingStatus) -> Mapping {
+        let mut m = Mapping::new(domain.to_string(), port);
+        m.status = status;
+        m
+    }
+
+    fn domains(rows: &[&Mapping]) -> Vec<String> {
+        rows.iter().map(|m| m.domain.clone()).collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        let api = mapping("api.localhost", 3000, MappingStatus::Active);
+        assert!(filter_matches("", &api));
+        assert!(filter_matches("apl", &api));
+        assert!(filter_matches("API", &api));
+        assert!(filter_matches("300", &api));
+        assert!(filter_matches("act", &api));
+        assert!(filter_matches("api 3000", &api));
+        assert!(!filter_matches("web", &api));
+        assert!(!filter_matches("api 4000", &api));
+
+        let mut web = mapping("web.localhost", 5173, MappingStatus::Active);
+        web.tags = parse_tags("frontend, team-a");
+        assert!(filter_matches("front", &web));
+        assert!(filter_matches("web team-a", &web));
+        assert!(!filter_matches("front", &api));
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(parse_tags(" Frontend,team-a  frontend "), ["frontend", "team-a"]);
+        assert!(parse_tags(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_sort_orders() {
+        let mut off = mapping("b.localhost", 1000, MappingStatus::Active);
+        off.enabled = false;
+        let mappings = vec![
+            mapping("c.localhost", 3000, MappingStatus::Active),
+            mapping("a.localhost", 5000, MappingStatus::PortUnreachable),
+            off,
+        ];
+        let sorted = |sort| domains(&visible_mappings(&mappings, "", sort));
+        assert_eq!(sorted(SortOrder::Added), ["c.localhost", "a.localhost", "b.localhost"]);
+        assert_eq!(sorted(SortOrder::Domain), ["a.localhost", "b.localhost", "c.localhost"]);
+        assert_eq!(sorted(SortOrder::Port), ["b.localhost", "c.localhost", "a.localhost"]);
+        assert_eq!(sorted(SortOrder::Status), ["a.localhost", "c.localhost", "b.localhost"]);
+        assert_eq!(sorted(SortOrder::Recent), ["b.localhost", "a.localhost", "c.localhost"]);
+    }
+
+    #[test]
+    fn test_selection_follows_mapping() {
+        let mappings = vec![
+            mapping("c.localhost", 3000, MappingStatus::Active),
+            mapping("a.localhost", 4000, MappingStatus::Active),
+        ];
+        let session = SessionInfo {
+            mode: RunMode::Rootless,
+            listen: Vec::new(),
+            self_update: true,
+        };
+        let mut state = TuiState::new(session);
+        let rows = state.visible(&mappings);
+        state.sync_selection(&rows);
+        assert_eq!(state.selected.as_deref(), Some("c.localhost"));
+
+        // Re-sorting moves the row, not the selection
+        state.sort = SortOrder::Domain;
+        let rows = state.visible(&mappings);
+        state.sync_selection(&rows);
+        assert_eq!(state.selected.as_deref(), Some("c.localhost"));
+        assert_eq!(state.selected_row, 1);
+        assert_eq!(state.selected_mapping(&mappings).unwrap().0, 0);
+
+        // Filtered out: the selection falls back to the nearest row
+        state.filter = "a.loc".to_string();
+        let rows = state.visible(&mappings);
+        state.sync_selection(&rows);
+        assert_eq!(state.selected.as_deref(), Some("a.localhost"));
     }
 }
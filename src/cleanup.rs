@@ -1,51 +1,69 @@
-use crate::hosts::manager::{sync_cleanup, HostsManager};
-use std::path::PathBuf;
+use crate::config::Config;
+use crate::hosts::backend::{self, HostsBackend};
+use crate::hosts::helper::HelperSetup;
+use crate::privilege::is_root;
+use crate::tui::terminal::restore_terminal;
+use std::sync::Arc;
 
-/// Install a panic hook that cleans up /etc/hosts before aborting.
-pub fn install_panic_hook(hosts_path: PathBuf) {
+/// Install a panic hook that undoes the backend's changes before aborting.
+pub fn install_panic_hook(hosts: Arc<dyn HostsBackend>) {
     let default_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
         // Attempt synchronous cleanup
-        sync_cleanup(&hosts_path);
+        let _ = hosts.restore_all();
         // Call the default hook to print the panic message
         default_hook(info);
     }));
 }
 
-/// Run the --cleanup command: remove all portmap entries from /etc/hosts.
-pub fn run_cleanup() -> anyhow::Result<()> {
-    let manager = HostsManager::new();
-    manager.restore_all()?;
-    println!("Cleaned up all portmap entries from /etc/hosts");
+/// Run the --cleanup command: remove all portmap entries from the configured backend.
+///
+/// As root, the backend is set up like the hosts helper's, from the system
+/// config rather than the invoking user's.
+pub fn run_cleanup(config: &Config) -> anyhow::Result<()> {
+    let hosts = if is_root() {
+        HelperSetup::from_config(config).backend()?
+    } else {
+        backend::from_config(config)?
+    };
+    hosts.restore_all()?;
+    println!("Cleaned up all portmap entries from {}", hosts.describe());
     Ok(())
 }
 
-/// Spawn a task that listens for Ctrl+C and SIGTERM, then cleans up.
+/// Spawn a task that listens for Ctrl+C and SIGTERM.
+///
+/// The first signal starts a graceful shutdown: the proxy drains and `main`
+/// cleans up afterwards. A second one cleans up and exits immediately.
 pub fn spawn_signal_handler(
-    hosts_path: PathBuf,
+    hosts: Arc<dyn HostsBackend>,
     shutdown: tokio::sync::watch::Sender<bool>,
 ) {
-    // Ctrl+C handler
-    let path = hosts_path.clone();
-    let shutdown_tx = shutdown.clone();
     tokio::spawn(async move {
-        let _ = tokio::signal::ctrl_c().await;
-        sync_cleanup(&path);
-        let _ = shutdown_tx.send(true);
-    });
+        #[cfg(unix)]
+        let mut sigterm =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("failed to install SIGTERM handler");
+
+        let mut received = 0;
+        loop {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = sigterm.recv() => {}
+            }
+            #[cfg(not(unix))]
+            let _ = tokio::signal::ctrl_c().await;
 
-    // SIGTERM handler (Unix only)
-    #[cfg(unix)]
-    {
-        let path = hosts_path;
-        let shutdown_tx = shutdown;
-        tokio::spawn(async move {
-            let mut sigterm =
-                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-                    .expect("failed to install SIGTERM handler");
-            sigterm.recv().await;
-            sync_cleanup(&path);
-            let _ = shutdown_tx.send(true);
-        });
-    }
+            received += 1;
+            if received == 1 {
+                let _ = shutdown.send(true);
+            } else {
+                let _ = hosts.restore_all();
+                // The TUI loop never gets to put the terminal back itself
+                let _ = restore_terminal();
+                std::process::exit(130);
+            }
+        }
+    });
 }
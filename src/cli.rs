@@ -0,0 +1,113 @@
+use anyhow::{anyhow, bail, Result};
+
+/// Command-line options.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cli {
+    /// `--cleanup`: remove leftover entries and exit
+    pub cleanup: bool,
+    /// `--rootless`: high port, no hosts edits, no sudo
+    pub rootless: bool,
+    /// `--port <N>`: proxy port
+    pub port: Option<u16>,
+    /// `--listen <ADDR>` (repeatable): proxy listen addresses
+    pub listen: Vec<String>,
+    /// `--version`: print the version and exit
+    pub version: bool,
+    /// `update [--rollback]`: update (or roll back) and exit
+    pub update: Option<UpdateAction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateAction {
+    Install,
+    Rollback,
+}
+
+impl Cli {
+    /// Parse the arguments after the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut cli = Cli::default();
+        let mut args = args.into_iter().peekable();
+        if args.peek().is_some_and(|a| a == "update") {
+            args.next();
+            cli.update = Some(UpdateAction::Install);
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--rollback" if cli.update.is_some() => cli.update = Some(UpdateAction::Rollback),
+                "--version" | "-V" => cli.version = true,
+                "--cleanup" => cli.cleanup = true,
+                "--rootless" => cli.rootless = true,
+                "--port" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--port needs a value"))?;
+                    cli.port = Some(parse_port(&value)?);
+                }
+                "--listen" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--listen needs a value"))?;
+                    cli.listen.push(value);
+                }
+                // Internal flag handled elsewhere
+                "--hosts-helper" => {}
+                other => {
+                    if let Some(value) = other.strip_prefix("--port=") {
+                        cli.port = Some(parse_port(value)?);
+                    } else if let Some(value) = other.strip_prefix("--listen=") {
+                        cli.listen.push(value.to_string());
+                    } else {
+                        bail!("Unknown argument: {}", other);
+                    }
+                }
+            }
+        }
+        Ok(cli)
+    }
+}
+
+fn parse_port(value: &str) -> Result<u16> {
+    match value.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => bail!("Invalid port: {}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli> {
+        Cli::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_flags() {
+        let cli = parse(&["--rootless", "--port", "8080"]).unwrap();
+        assert!(cli.rootless);
+        assert_eq!(cli.port, Some(8080));
+        assert_eq!(parse(&["--port=9000"]).unwrap().port, Some(9000));
+        assert!(parse(&["--cleanup"]).unwrap().cleanup);
+
+        let cli = parse(&["--listen", "127.0.0.1:80", "--listen=[::1]:80"]).unwrap();
+        assert_eq!(cli.listen, vec!["127.0.0.1:80", "[::1]:80"]);
+    }
+
+    #[test]
+    fn test_update_command() {
+        assert_eq!(parse(&["update"]).unwrap().update, Some(UpdateAction::Install));
+        assert_eq!(
+            parse(&["update", "--rollback"]).unwrap().update,
+            Some(UpdateAction::Rollback)
+        );
+        assert_eq!(parse(&[]).unwrap().update, None);
+        assert!(parse(&["--rollback"]).is_err());
+        assert!(parse(&["--version"]).unwrap().version);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(parse(&["--port"]).is_err());
+        assert!(parse(&["--listen"]).is_err());
+        assert!(parse(&["--port", "0"]).is_err());
+        assert!(parse(&["--port", "http"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+    }
+}
@@ -0,0 +1,587 @@
+use crate::probe::StatusRange;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+/// User configuration, loaded from `~/.config/portmap/config.toml`.
+///
+/// Every field has a default, so a missing file (or a missing section)
+/// behaves exactly like an empty one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub proxy: ProxyConfig,
+    pub hosts: HostsConfig,
+    pub dns: DnsConfig,
+    pub privileges: PrivilegesConfig,
+    pub update: UpdateConfig,
+    pub health: HealthConfig,
+    pub notifications: NotificationsConfig,
+    pub tui: TuiConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// Proxy port; defaults to 80, or 8080 in rootless mode
+    pub port: Option<u16>,
+    /// Listen addresses (`127.0.0.1:80`, `[::1]:80`, or a bare IP that uses
+    /// `port`). Empty means loopback only, IPv4 and IPv6.
+    pub listen: Vec<String>,
+    /// Run without sudo on an unprivileged port, leaving /etc/hosts alone
+    pub rootless: bool,
+    /// On quit, how long in-flight connections may take to finish before
+    /// they are cut off
+    pub drain_timeout_secs: u64,
+    /// HTML served for disabled mappings, instead of the built-in page;
+    /// `{domain}` is replaced with the mapping's domain
+    pub disabled_page: Option<PathBuf>,
+}
+
+impl ProxyConfig {
+    /// Default port when rootless and none is configured.
+    pub const ROOTLESS_PORT: u16 = 8080;
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            port: None,
+            listen: Vec::new(),
+            rootless: false,
+            drain_timeout_secs: 10,
+            disabled_page: None,
+        }
+    }
+}
+
+/// Defaults for mapping status probes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Probe timeout, also used for plain TCP checks
+    pub timeout_ms: u64,
+    /// Status codes a health check accepts, e.g. `"200-399"`
+    pub expected: StatusRange,
+    /// Time between probe rounds while statuses are changing
+    pub interval_ms: u64,
+    /// The interval doubles up to this while nothing changes
+    pub max_interval_ms: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 2000,
+            expected: StatusRange::default(),
+            interval_ms: 3000,
+            max_interval_ms: 30000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuiConfig {
+    /// Ask before deleting a mapping (deletes can always be undone with `u`)
+    pub confirm_delete: bool,
+    /// Keys per action (`add = ["a"]`), replacing that action's defaults
+    pub keys: BTreeMap<String, Vec<String>>,
+    /// `dark` (default), `light`, `high-contrast`, `monochrome`, or one of
+    /// `themes`
+    pub theme: Option<String>,
+    /// User themes by name
+    pub themes: BTreeMap<String, ThemeConfig>,
+}
+
+/// A user theme: a built-in one with some styles replaced. Styles are
+/// written like `bold #56b4e9 on black`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Built-in theme to start from (default `dark`)
+    pub base: Option<String>,
+    pub accent: Option<String>,
+    pub heading: Option<String>,
+    pub text: Option<String>,
+    pub muted: Option<String>,
+    pub ok: Option<String>,
+    pub warn: Option<String>,
+    pub error: Option<String>,
+    pub notice: Option<String>,
+    pub selected: Option<String>,
+}
+
+/// What happens when a mapping changes status.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    /// Send a desktop notification over D-Bus
+    pub desktop: bool,
+    /// Command (argv) to run on each change, with `PORTMAP_DOMAIN`,
+    /// `PORTMAP_PORT`, `PORTMAP_STATUS` and `PORTMAP_PREVIOUS_STATUS` set
+    pub hook: Vec<String>,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            desktop: true,
+            hook: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateConfig {
+    /// Check for new releases at startup (`PORTMAP_NO_UPDATE=1` also disables it)
+    pub enabled: bool,
+    pub channel: UpdateChannel,
+    /// GitHub-style "list releases" endpoint (`PORTMAP_RELEASE_URL` overrides it)
+    pub release_url: String,
+    /// Minisign public key release archives must be signed with; defaults
+    /// to the key built into release binaries
+    pub public_key: Option<String>,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            channel: UpdateChannel::Stable,
+            release_url: "https://api.github.com/repos/filiphric/portmap/releases".to_string(),
+            public_key: None,
+        }
+    }
+}
+
+/// Which releases the updater considers.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    /// Stable releases plus pre-releases
+    Prerelease,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivilegesConfig {
+    /// Drop to the sudo-invoking user once sockets are bound, leaving
+    /// hosts edits to a small root helper process.
+    pub drop: bool,
+}
+
+impl Default for PrivilegesConfig {
+    fn default() -> Self {
+        Self { drop: true }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostsConfig {
+    /// How mapped domains are made to resolve.
+    pub backend: ResolverBackend,
+    /// Which loopback addresses each domain is written with.
+    pub ip_family: IpFamily,
+    /// Comment out existing entries that map the same domain elsewhere,
+    /// for as long as the mapping exists.
+    pub comment_out_conflicts: bool,
+    pub dnsmasq: DnsmasqConfig,
+    pub resolved: ResolvedConfig,
+}
+
+impl Default for HostsConfig {
+    fn default() -> Self {
+        Self {
+            backend: ResolverBackend::HostsFile,
+            ip_family: IpFamily::Dual,
+            comment_out_conflicts: false,
+            dnsmasq: DnsmasqConfig::default(),
+            resolved: ResolvedConfig::default(),
+        }
+    }
+}
+
+/// Name-resolution backends (see `hosts::backend`).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolverBackend {
+    /// Sentinel block in /etc/hosts
+    HostsFile,
+    /// Drop-in file in a dnsmasq config directory
+    Dnsmasq,
+    /// Per-link routing domains via `resolvectl`
+    Resolved,
+    /// Don't touch system resolution (e.g. when only the DNS responder is used)
+    None,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DnsmasqConfig {
+    /// Directory dnsmasq reads drop-ins from, e.g. `/etc/NetworkManager/dnsmasq.d`
+    pub dir: PathBuf,
+    /// Command run after each change; empty to skip reloading
+    pub reload: Vec<String>,
+}
+
+impl Default for DnsmasqConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/etc/dnsmasq.d"),
+            reload: ["systemctl", "reload-or-restart", "dnsmasq"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResolvedConfig {
+    /// Path to the `resolvectl` binary
+    pub resolvectl: PathBuf,
+    /// Network link the routing domains are attached to; must be set, and
+    /// can't be `lo` (systemd-resolved ignores DNS settings on loopback)
+    pub link: String,
+    /// DNS server queried for the mapped domains on that link; defaults to
+    /// portmap's own responder (`dns.listen`), which then has to be enabled
+    pub dns: Option<String>,
+}
+
+impl Default for ResolvedConfig {
+    fn default() -> Self {
+        Self {
+            resolvectl: PathBuf::from("resolvectl"),
+            link: String::new(),
+            dns: None,
+        }
+    }
+}
+
+impl ResolvedConfig {
+    /// The DNS server to give the link, checking the setup can work.
+    pub fn server(&self, dns: &DnsConfig) -> Result<String> {
+        if self.link.is_empty() {
+            bail!("hosts.resolved.link must name the network link to route the mapped domains on");
+        }
+        if self.link == "lo" {
+            bail!(
+                "hosts.resolved.link can't be \"lo\": systemd-resolved doesn't use DNS servers \
+                 set on loopback; create a link for it (e.g. a dummy interface) instead"
+            );
+        }
+        match &self.dns {
+            Some(server) => Ok(server.clone()),
+            None if dns.enabled => Ok(dns.listen.to_string()),
+            None => bail!(
+                "The resolved backend routes domains to portmap's DNS responder; \
+                 set dns.enabled = true (or hosts.resolved.dns)"
+            ),
+        }
+    }
+}
+
+/// Address families written to the hosts file for each mapped domain.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    /// `127.0.0.1` only
+    V4,
+    /// `::1` only
+    V6,
+    /// Both `127.0.0.1` and `::1`
+    Dual,
+}
+
+impl IpFamily {
+    /// Loopback addresses for this family, IPv4 first.
+    pub fn loopback_ips(self) -> &'static [&'static str] {
+        match self {
+            IpFamily::V4 => &["127.0.0.1"],
+            IpFamily::V6 => &["::1"],
+            IpFamily::Dual => &["127.0.0.1", "::1"],
+        }
+    }
+}
+
+/// Built-in DNS responder for the mapped domains.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DnsConfig {
+    pub enabled: bool,
+    /// Address the UDP and TCP listeners bind to
+    pub listen: SocketAddr,
+    /// Resolver that unmapped names are forwarded to; NXDOMAIN if unset
+    pub upstream: Option<SocketAddr>,
+    /// Addresses returned for mapped domains (A and AAAA)
+    pub addresses: Vec<IpAddr>,
+    /// TTL of the answers, in seconds
+    pub ttl: u32,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: SocketAddr::from(([127, 0, 0, 1], 1053)),
+            upstream: None,
+            addresses: vec![
+                IpAddr::from([127, 0, 0, 1]),
+                IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1]),
+            ],
+            ttl: 5,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file, falling back to defaults if it doesn't exist.
+    pub fn load() -> Result<Self> {
+        let Some(path) = config_path() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content)
+                .with_context(|| format!("Invalid config file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Load the system-wide config the root hosts helper takes its backend
+    /// settings from, falling back to defaults if it doesn't exist.
+    pub fn load_system() -> Result<Self> {
+        Self::load_root_owned(Path::new(SYSTEM_CONFIG_PATH))
+    }
+
+    /// Like `load`, but only trusts a file no one but root can change: its
+    /// commands and paths are used as root.
+    fn load_root_owned(path: &Path) -> Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+            bail!(
+                "{} must be owned by root and writable only by root",
+                path.display()
+            );
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+}
+
+/// System-wide config, read by the root hosts helper for `[hosts.dnsmasq]`
+/// and `[hosts.resolved]` when privileges are dropped.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/portmap/config.toml";
+
+/// Location of the config file.
+///
+/// `$PORTMAP_CONFIG` wins; otherwise `$XDG_CONFIG_HOME/portmap/config.toml`,
+/// then `~/.config/portmap/config.toml` of the invoking (non-sudo) user.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("PORTMAP_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("portmap").join("config.toml"));
+    }
+    Some(user_home()?.join(".config").join("portmap").join("config.toml"))
+}
+
+/// Home directory of the user who launched portmap.
+///
+/// Under sudo, `$HOME` may point at root's home, so the `SUDO_USER`'s
+/// passwd entry is preferred.
+pub fn user_home() -> Option<PathBuf> {
+    if let Ok(user) = std::env::var("SUDO_USER") {
+        if let Some(home) = passwd_home(&user) {
+            return Some(home);
+        }
+    }
+    std::env::var_os("HOME").map(PathBuf::from)
+}
+
+fn passwd_home(user: &str) -> Option<PathBuf> {
+    let name = std::ffi::CString::new(user).ok()?;
+    // SAFETY: getpwnam returns a pointer to static storage or null; we copy
+    // the directory out before any other passwd call can overwrite it.
+    unsafe {
+        let pw = libc::getpwnam(name.as_ptr());
+        if pw.is_null() || (*pw).pw_dir.is_null() {
+            return None;
+        }
+        let dir = std::ffi::CStr::from_ptr((*pw).pw_dir);
+        Some(PathBuf::from(dir.to_string_lossy().into_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.hosts.ip_family, IpFamily::Dual);
+        assert!(!config.hosts.comment_out_conflicts);
+    }
+
+    #[test]
+    fn test_ip_family() {
+        let config = Config::parse("[hosts]\nip_family = \"v4\"\n").unwrap();
+        assert_eq!(config.hosts.ip_family, IpFamily::V4);
+        assert_eq!(config.hosts.ip_family.loopback_ips(), &["127.0.0.1"]);
+    }
+
+    #[test]
+    fn test_backend_selection() {
+        let config = Config::parse(
+            "[hosts]\nbackend = \"dnsmasq\"\n[hosts.dnsmasq]\ndir = \"/tmp/d\"\nreload = []\n",
+        )
+        .unwrap();
+        assert_eq!(config.hosts.backend, ResolverBackend::Dnsmasq);
+        assert_eq!(config.hosts.dnsmasq.dir, PathBuf::from("/tmp/d"));
+        assert!(config.hosts.dnsmasq.reload.is_empty());
+
+        let config = Config::parse("[hosts]\nbackend = \"hosts-file\"\n").unwrap();
+        assert_eq!(config.hosts.backend, ResolverBackend::HostsFile);
+    }
+
+    #[test]
+    fn test_resolved_server() {
+        let mut config = Config::parse("[hosts]\nbackend = \"resolved\"\n").unwrap();
+        let err = config.hosts.resolved.server(&config.dns).unwrap_err();
+        assert!(err.to_string().contains("link"));
+
+        config.hosts.resolved.link = "lo".to_string();
+        let err = config.hosts.resolved.server(&config.dns).unwrap_err();
+        assert!(err.to_string().contains("loopback"));
+
+        config.hosts.resolved.link = "portmap0".to_string();
+        let err = config.hosts.resolved.server(&config.dns).unwrap_err();
+        assert!(err.to_string().contains("dns.enabled"));
+
+        // Defaults to wherever the responder listens
+        config.dns.enabled = true;
+        assert_eq!(config.hosts.resolved.server(&config.dns).unwrap(), "127.0.0.1:1053");
+        config.dns.listen = "[::1]:5353".parse().unwrap();
+        assert_eq!(config.hosts.resolved.server(&config.dns).unwrap(), "[::1]:5353");
+
+        config.hosts.resolved.dns = Some("10.0.0.2".to_string());
+        assert_eq!(config.hosts.resolved.server(&config.dns).unwrap(), "10.0.0.2");
+    }
+
+    #[test]
+    fn test_dns_config() {
+        let config = Config::parse(
+            "[dns]\nenabled = true\nlisten = \"127.0.0.1:53\"\nupstream = \"1.1.1.1:53\"\naddresses = [\"192.168.1.10\"]\n",
+        )
+        .unwrap();
+        assert!(config.dns.enabled);
+        assert_eq!(config.dns.listen, SocketAddr::from(([127, 0, 0, 1], 53)));
+        assert_eq!(config.dns.upstream, Some(SocketAddr::from(([1, 1, 1, 1], 53))));
+        assert_eq!(config.dns.addresses, vec![IpAddr::from([192, 168, 1, 10])]);
+    }
+
+    #[test]
+    fn test_proxy_config() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.proxy.port, None);
+        assert!(!config.proxy.rootless);
+        assert_eq!(config.proxy.drain_timeout_secs, 10);
+
+        let config =
+            Config::parse("[proxy]\nport = 8443\nrootless = true\ndrain_timeout_secs = 2\n")
+                .unwrap();
+        assert_eq!(config.proxy.port, Some(8443));
+        assert!(config.proxy.rootless);
+        assert_eq!(config.proxy.drain_timeout_secs, 2);
+
+        let config = Config::parse("[proxy]\nlisten = [\"127.0.0.1:80\", \"192.168.1.5\"]\n").unwrap();
+        assert_eq!(config.proxy.listen, vec!["127.0.0.1:80", "192.168.1.5"]);
+    }
+
+    #[test]
+    fn test_update_config() {
+        let config = Config::parse("").unwrap();
+        assert!(config.update.enabled);
+        assert_eq!(config.update.channel, UpdateChannel::Stable);
+
+        let config = Config::parse(
+            "[update]\nenabled = false\nchannel = \"prerelease\"\nrelease_url = \"http://127.0.0.1:9/releases\"\n",
+        )
+        .unwrap();
+        assert!(!config.update.enabled);
+        assert_eq!(config.update.channel, UpdateChannel::Prerelease);
+        assert_eq!(config.update.release_url, "http://127.0.0.1:9/releases");
+    }
+
+    #[test]
+    fn test_health_config() {
+        let config = Config::parse("[health]\ntimeout_ms = 500\nexpected = \"200-299\"\n").unwrap();
+        assert_eq!(config.health.timeout_ms, 500);
+        assert_eq!(config.health.interval_ms, 3000);
+        assert_eq!(config.health.expected, StatusRange { min: 200, max: 299 });
+        assert!(Config::parse("[health]\nexpected = \"2xx\"\n").is_err());
+    }
+
+    #[test]
+    fn test_notifications_config() {
+        let config = Config::default();
+        assert!(config.notifications.desktop);
+        assert!(config.notifications.hook.is_empty());
+
+        let config = Config::parse(
+            "[notifications]\ndesktop = false\nhook = [\"notify-send\", \"portmap\"]\n",
+        )
+        .unwrap();
+        assert!(!config.notifications.desktop);
+        assert_eq!(config.notifications.hook, vec!["notify-send", "portmap"]);
+    }
+
+    #[test]
+    fn test_tui_config() {
+        assert!(!Config::default().tui.confirm_delete);
+        let config = Config::parse("[tui]\nconfirm_delete = true\n").unwrap();
+        assert!(config.tui.confirm_delete);
+
+        let config = Config::parse("[tui.keys]\ndelete = [\"x\", \"delete\"]\n").unwrap();
+        assert_eq!(config.tui.keys["delete"], ["x", "delete"]);
+    }
+
+    #[test]
+    fn test_system_config_must_be_root_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::TempDir::new().unwrap();
+        let missing = Config::load_root_owned(&dir.path().join("config.toml")).unwrap();
+        assert_eq!(missing.hosts.backend, ResolverBackend::HostsFile);
+
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[hosts.dnsmasq]\nreload = [\"sh\", \"-c\", \"id\"]\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o666)).unwrap();
+        let err = Config::load_root_owned(&path).unwrap_err();
+        assert!(err.to_string().contains("owned by root"));
+    }
+
+    #[test]
+    fn test_unknown_key_rejected() {
+        assert!(Config::parse("[hosts]\nipfamily = \"v4\"\n").is_err());
+    }
+}
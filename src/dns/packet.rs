@@ -0,0 +1,224 @@
+use std::net::IpAddr;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_AAAA: u16 = 28;
+pub const CLASS_IN: u16 = 1;
+pub const CLASS_ANY: u16 = 255;
+
+pub const RCODE_NOERROR: u16 = 0;
+pub const RCODE_NXDOMAIN: u16 = 3;
+pub const RCODE_NOTIMP: u16 = 4;
+
+const HEADER_LEN: usize = 12;
+const FLAG_QR: u16 = 0x8000;
+const FLAG_AA: u16 = 0x0400;
+const FLAG_RD: u16 = 0x0100;
+const FLAG_RA: u16 = 0x0080;
+const OPCODE_MASK: u16 = 0x7800;
+
+/// A parsed single-question DNS query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub id: u16,
+    pub flags: u16,
+    /// Lowercased name without the trailing dot
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+    /// End offset of the question section in the original packet
+    question_end: usize,
+}
+
+impl Query {
+    /// Whether this is a standard query (opcode 0).
+    pub fn is_standard(&self) -> bool {
+        self.flags & OPCODE_MASK == 0
+    }
+}
+
+/// Parse a query packet. Returns `None` for responses, malformed packets
+/// and anything other than exactly one question.
+pub fn parse_query(packet: &[u8]) -> Option<Query> {
+    if packet.len() < HEADER_LEN {
+        return None;
+    }
+    let id = u16::from_be_bytes([packet[0], packet[1]]);
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    if flags & FLAG_QR != 0 || qdcount != 1 {
+        return None;
+    }
+
+    let mut pos = HEADER_LEN;
+    let mut labels = Vec::new();
+    loop {
+        let len = *packet.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers and extended label types never appear in
+        // a well-formed question
+        if len > 63 {
+            return None;
+        }
+        let label = packet.get(pos..pos + len)?;
+        labels.push(String::from_utf8_lossy(label).to_lowercase());
+        pos += len;
+    }
+
+    let fixed = packet.get(pos..pos + 4)?;
+    let qtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+    let qclass = u16::from_be_bytes([fixed[2], fixed[3]]);
+
+    Some(Query {
+        id,
+        flags,
+        name: labels.join("."),
+        qtype,
+        qclass,
+        question_end: pos + 4,
+    })
+}
+
+/// Build a response to `query`, echoing its question and answering with
+/// every address in `answers` that matches the query type.
+pub fn build_response(
+    query: &Query,
+    packet: &[u8],
+    rcode: u16,
+    answers: &[IpAddr],
+    ttl: u32,
+    recursion_available: bool,
+) -> Vec<u8> {
+    let records: Vec<&IpAddr> = answers
+        .iter()
+        .filter(|ip| match ip {
+            IpAddr::V4(_) => query.qtype == TYPE_A,
+            IpAddr::V6(_) => query.qtype == TYPE_AAAA,
+        })
+        .collect();
+
+    let mut flags = FLAG_QR | FLAG_AA | (query.flags & (OPCODE_MASK | FLAG_RD)) | (rcode & 0xf);
+    if recursion_available {
+        flags |= FLAG_RA;
+    }
+
+    let mut out = Vec::with_capacity(query.question_end + records.len() * 28);
+    out.extend_from_slice(&query.id.to_be_bytes());
+    out.extend_from_slice(&flags.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes()); // qdcount
+    out.extend_from_slice(&(records.len() as u16).to_be_bytes()); // ancount
+    out.extend_from_slice(&0u16.to_be_bytes()); // nscount
+    out.extend_from_slice(&0u16.to_be_bytes()); // arcount
+    out.extend_from_slice(&packet[HEADER_LEN..query.question_end]);
+
+    for ip in records {
+        // Name is a pointer back to the question at offset 12
+        out.extend_from_slice(&[0xc0, HEADER_LEN as u8]);
+        let (rtype, rdata) = match ip {
+            IpAddr::V4(v4) => (TYPE_A, v4.octets().to_vec()),
+            IpAddr::V6(v6) => (TYPE_AAAA, v6.octets().to_vec()),
+        };
+        out.extend_from_slice(&rtype.to_be_bytes());
+        out.extend_from_slice(&CLASS_IN.to_be_bytes());
+        out.extend_from_slice(&ttl.to_be_bytes());
+        out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        out.extend_from_slice(&rdata);
+    }
+
+    out
+}
+
+/// Build a query packet (for tests).
+#[cfg(test)]
+pub fn build_query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&id.to_be_bytes());
+    out.extend_from_slice(&FLAG_RD.to_be_bytes());
+    out.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    out.extend_from_slice(&qtype.to_be_bytes());
+    out.extend_from_slice(&CLASS_IN.to_be_bytes());
+    out
+}
+
+/// Response code and answer addresses of a response packet (for tests).
+#[cfg(test)]
+pub fn parse_response(packet: &[u8]) -> (u16, Vec<IpAddr>) {
+    let query = parse_query(&{
+        // Reuse the question parser by clearing the QR bit
+        let mut p = packet.to_vec();
+        p[2] &= 0x7f;
+        p
+    })
+    .unwrap();
+    let rcode = u16::from_be_bytes([packet[2], packet[3]]) & 0xf;
+    let ancount = u16::from_be_bytes([packet[6], packet[7]]);
+    let mut pos = query.question_end;
+    let mut ips = Vec::new();
+    for _ in 0..ancount {
+        pos += 2; // name pointer
+        let rtype = u16::from_be_bytes([packet[pos], packet[pos + 1]]);
+        pos += 8; // type, class, ttl
+        let len = u16::from_be_bytes([packet[pos], packet[pos + 1]]) as usize;
+        pos += 2;
+        let data = &packet[pos..pos + len];
+        ips.push(match rtype {
+            TYPE_A => IpAddr::from(<[u8; 4]>::try_from(data).unwrap()),
+            _ => IpAddr::from(<[u8; 16]>::try_from(data).unwrap()),
+        });
+        pos += len;
+    }
+    (rcode, ips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        let packet = build_query(0x1234, "My-Project.localhost", TYPE_AAAA);
+        let query = parse_query(&packet).unwrap();
+        assert_eq!(query.id, 0x1234);
+        assert_eq!(query.name, "my-project.localhost");
+        assert_eq!(query.qtype, TYPE_AAAA);
+        assert_eq!(query.qclass, CLASS_IN);
+        assert!(query.is_standard());
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed() {
+        assert!(parse_query(&[0; 5]).is_none());
+        let mut packet = build_query(1, "a.localhost", TYPE_A);
+        packet.truncate(packet.len() - 3);
+        assert!(parse_query(&packet).is_none());
+    }
+
+    #[test]
+    fn test_response_filters_by_type() {
+        let answers: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+        let packet = build_query(7, "a.localhost", TYPE_A);
+        let query = parse_query(&packet).unwrap();
+        let response = build_response(&query, &packet, RCODE_NOERROR, &answers, 5, false);
+        assert_eq!(&response[..2], &[0, 7]);
+        let (rcode, ips) = parse_response(&response);
+        assert_eq!(rcode, RCODE_NOERROR);
+        assert_eq!(ips, vec!["127.0.0.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn test_nxdomain_has_no_answers() {
+        let packet = build_query(9, "nope.localhost", TYPE_A);
+        let query = parse_query(&packet).unwrap();
+        let response = build_response(&query, &packet, RCODE_NXDOMAIN, &[], 5, false);
+        let (rcode, ips) = parse_response(&response);
+        assert_eq!(rcode, RCODE_NXDOMAIN);
+        assert!(ips.is_empty());
+    }
+}
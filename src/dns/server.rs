@@ -0,0 +1,316 @@
+use crate::app::{find_mapping, Mapping};
+use crate::config::DnsConfig;
+use crate::dns::packet::{
+    build_response, parse_query, CLASS_ANY, CLASS_IN, RCODE_NOERROR, RCODE_NOTIMP,
+    RCODE_NXDOMAIN,
+};
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::watch;
+
+/// How long to wait for the upstream resolver before giving up.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Pause after a failed TCP accept, so e.g. running out of file
+/// descriptors doesn't spin the loop.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Largest DNS message over UDP (EDNS payloads included).
+const MAX_UDP_PACKET: usize = 4096;
+
+/// A small DNS server answering A/AAAA queries for mapped domains.
+pub struct DnsServer {
+    udp: Arc<UdpSocket>,
+    tcp: TcpListener,
+    config: Arc<DnsConfig>,
+}
+
+impl DnsServer {
+    /// Bind the UDP and TCP sockets on the configured address.
+    pub async fn bind(config: DnsConfig) -> Result<Self> {
+        let udp = UdpSocket::bind(config.listen)
+            .await
+            .with_context(|| format!("Failed to bind DNS (UDP) to {}", config.listen))?;
+        // Use UDP's actual address so port 0 gives both the same port
+        let addr = udp.local_addr()?;
+        let tcp = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind DNS (TCP) to {}", addr))?;
+        Ok(Self {
+            udp: Arc::new(udp),
+            tcp,
+            config: Arc::new(config),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.udp.local_addr().expect("bound socket has an address")
+    }
+
+    /// Serve queries until the shutdown signal is received.
+    pub async fn run(
+        self,
+        mappings_rx: watch::Receiver<Vec<Mapping>>,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) -> Result<()> {
+        let mut buf = vec![0u8; MAX_UDP_PACKET];
+        loop {
+            tokio::select! {
+                result = self.udp.recv_from(&mut buf) => {
+                    // One bad datagram (or an ICMP error surfacing here)
+                    // mustn't take the responder down
+                    let (len, peer) = match result {
+                        Ok(received) => received,
+                        Err(e) => {
+                            eprintln!("DNS receive error: {}", e);
+                            continue;
+                        }
+                    };
+                    let packet = buf[..len].to_vec();
+                    let udp = self.udp.clone();
+                    let config = self.config.clone();
+                    let rx = mappings_rx.clone();
+                    tokio::spawn(async move {
+                        let mappings = rx.borrow().clone();
+                        if let Some(response) = answer(&packet, &mappings, &config).await {
+                            let _ = udp.send_to(&response, peer).await;
+                        }
+                    });
+                }
+                result = self.tcp.accept() => {
+                    let (stream, _addr) = match result {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            eprintln!("DNS accept error: {}", e);
+                            // Out of file descriptors, most likely; give it a moment
+                            tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                            continue;
+                        }
+                    };
+                    let config = self.config.clone();
+                    let rx = mappings_rx.clone();
+                    tokio::spawn(async move {
+                        let _ = serve_tcp(stream, rx, config).await;
+                    });
+                }
+                _ = shutdown_rx.changed() => {
+                    if *shutdown_rx.borrow() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serve length-prefixed queries on a TCP connection until the client closes it.
+async fn serve_tcp(
+    mut stream: TcpStream,
+    mappings_rx: watch::Receiver<Vec<Mapping>>,
+    config: Arc<DnsConfig>,
+) -> Result<()> {
+    loop {
+        let len = match stream.read_u16().await {
+            Ok(len) => len as usize,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut packet = vec![0u8; len];
+        stream.read_exact(&mut packet).await?;
+
+        let mappings = mappings_rx.borrow().clone();
+        if let Some(response) = answer(&packet, &mappings, &config).await {
+            stream.write_u16(response.len() as u16).await?;
+            stream.write_all(&response).await?;
+        }
+    }
+}
+
+/// Produce the response for one query packet, or `None` to drop it.
+async fn answer(packet: &[u8], mappings: &[Mapping], config: &DnsConfig) -> Option<Vec<u8>> {
+    let query = parse_query(packet)?;
+    let recursion = config.upstream.is_some();
+
+    if !query.is_standard() {
+        return Some(build_response(&query, packet, RCODE_NOTIMP, &[], 0, recursion));
+    }
+
+    let class_ok = query.qclass == CLASS_IN || query.qclass == CLASS_ANY;
+    match find_mapping(mappings, &query.name) {
+        // Disabled mappings stop resolving, without falling through upstream
+        Some(mapping) if !mapping.enabled => {
+            return Some(build_response(&query, packet, RCODE_NXDOMAIN, &[], 0, recursion));
+        }
+        // Non-address types get an empty NOERROR answer (NODATA)
+        Some(_) if class_ok => {
+            return Some(build_response(
+                &query,
+                packet,
+                RCODE_NOERROR,
+                &config.addresses,
+                config.ttl,
+                recursion,
+            ));
+        }
+        _ => {}
+    }
+
+    if let Some(upstream) = config.upstream {
+        if let Ok(response) = forward(packet, upstream).await {
+            return Some(response);
+        }
+    }
+
+    Some(build_response(&query, packet, RCODE_NXDOMAIN, &[], 0, recursion))
+}
+
+/// Relay a query to the upstream resolver over UDP.
+async fn forward(packet: &[u8], upstream: SocketAddr) -> Result<Vec<u8>> {
+    let bind: SocketAddr = if upstream.is_ipv4() {
+        "0.0.0.0:0".parse()?
+    } else {
+        "[::]:0".parse()?
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(upstream).await?;
+    socket.send(packet).await?;
+
+    let mut buf = vec![0u8; MAX_UDP_PACKET];
+    let len = tokio::time::timeout(UPSTREAM_TIMEOUT, socket.recv(&mut buf))
+        .await
+        .context("Upstream DNS timed out")??;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::packet::{build_query, parse_response, TYPE_A, TYPE_AAAA};
+    use std::net::IpAddr;
+
+    fn mapping(domain: &str) -> Mapping {
+        Mapping::new(domain.to_string(), 3000)
+    }
+
+    async fn start(config: DnsConfig) -> (SocketAddr, watch::Sender<bool>) {
+        let server = DnsServer::bind(config).await.unwrap();
+        let addr = server.local_addr();
+        let (_mappings_tx, mappings_rx) = watch::channel(vec![mapping("api.localhost")]);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        tokio::spawn(async move {
+            let _keep = _mappings_tx;
+            server.run(mappings_rx, shutdown_rx).await.unwrap();
+        });
+        (addr, shutdown_tx)
+    }
+
+    fn test_config() -> DnsConfig {
+        DnsConfig {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            ..DnsConfig::default()
+        }
+    }
+
+    async fn udp_query(addr: SocketAddr, name: &str, qtype: u16) -> (u16, Vec<IpAddr>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket
+            .send_to(&build_query(42, name, qtype), addr)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 512];
+        let len = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        parse_response(&buf[..len])
+    }
+
+    #[tokio::test]
+    async fn test_answers_mapped_domains_over_udp() {
+        let (addr, _shutdown) = start(test_config()).await;
+
+        let (rcode, ips) = udp_query(addr, "api.localhost", TYPE_A).await;
+        assert_eq!(rcode, RCODE_NOERROR);
+        assert_eq!(ips, vec!["127.0.0.1".parse::<IpAddr>().unwrap()]);
+
+        let (_, ips) = udp_query(addr, "api.localhost", TYPE_AAAA).await;
+        assert_eq!(ips, vec!["::1".parse::<IpAddr>().unwrap()]);
+
+        // Subdomains resolve through their parent mapping
+        let (rcode, ips) = udp_query(addr, "tenant.api.localhost", TYPE_A).await;
+        assert_eq!(rcode, RCODE_NOERROR);
+        assert_eq!(ips.len(), 1);
+
+        let (rcode, ips) = udp_query(addr, "other.localhost", TYPE_A).await;
+        assert_eq!(rcode, RCODE_NXDOMAIN);
+        assert!(ips.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_answers_over_tcp() {
+        let (addr, _shutdown) = start(test_config()).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let query = build_query(1, "api.localhost", TYPE_A);
+        stream.write_u16(query.len() as u16).await.unwrap();
+        stream.write_all(&query).await.unwrap();
+
+        let len = stream.read_u16().await.unwrap() as usize;
+        let mut response = vec![0u8; len];
+        stream.read_exact(&mut response).await.unwrap();
+        let (rcode, ips) = parse_response(&response);
+        assert_eq!(rcode, RCODE_NOERROR);
+        assert_eq!(ips, vec!["127.0.0.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_mappings_are_nxdomain() {
+        let mut disabled = mapping("api.localhost");
+        disabled.enabled = false;
+        let mappings = vec![disabled, mapping("web.localhost")];
+        // Even with an upstream configured, nothing is forwarded
+        let config = DnsConfig {
+            upstream: Some("127.0.0.1:9".parse().unwrap()),
+            ..test_config()
+        };
+
+        for name in ["api.localhost", "tenant.api.localhost"] {
+            let query = build_query(7, name, TYPE_A);
+            let response = answer(&query, &mappings, &config).await.unwrap();
+            assert_eq!(parse_response(&response), (RCODE_NXDOMAIN, vec![]));
+        }
+        let query = build_query(7, "web.localhost", TYPE_A);
+        let response = answer(&query, &mappings, &config).await.unwrap();
+        assert_eq!(parse_response(&response).0, RCODE_NOERROR);
+    }
+
+    #[tokio::test]
+    async fn test_forwards_unknown_names_upstream() {
+        // Fake upstream that answers every query with 192.0.2.1
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = upstream.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (len, peer) = upstream.recv_from(&mut buf).await.unwrap();
+            let query = parse_query(&buf[..len]).unwrap();
+            let answers = ["192.0.2.1".parse().unwrap()];
+            let response = build_response(&query, &buf[..len], RCODE_NOERROR, &answers, 60, true);
+            upstream.send_to(&response, peer).await.unwrap();
+        });
+
+        let config = DnsConfig {
+            upstream: Some(upstream_addr),
+            ..test_config()
+        };
+        let (addr, _shutdown) = start(config).await;
+        let (rcode, ips) = udp_query(addr, "example.com", TYPE_A).await;
+        assert_eq!(rcode, RCODE_NOERROR);
+        assert_eq!(ips, vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+    }
+}
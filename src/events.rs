@@ -0,0 +1,380 @@
+//! Status change events.
+//!
+//! Whenever a mapping's status changes (say a dev server crashes, or comes
+//! back), a desktop notification is sent over D-Bus and/or a user hook is
+//! run with the details in its environment.
+
+use crate::app::MappingStatus;
+use crate::config::NotificationsConfig;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::process::Stdio;
+use tokio::sync::mpsc;
+use zbus::zvariant::Value;
+use zbus::Connection;
+
+/// A mapping changed status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusEvent {
+    pub domain: String,
+    pub port: u16,
+    pub previous: MappingStatus,
+    pub status: MappingStatus,
+}
+
+impl StatusEvent {
+    /// An event for a status change, if it is one worth reporting. The
+    /// first probe after adding a mapping (from `Unknown`) isn't.
+    pub fn transition(
+        domain: &str,
+        port: u16,
+        previous: &MappingStatus,
+        status: &MappingStatus,
+    ) -> Option<Self> {
+        if previous == status || *previous == MappingStatus::Unknown {
+            return None;
+        }
+        Some(Self {
+            domain: domain.to_string(),
+            port,
+            previous: previous.clone(),
+            status: status.clone(),
+        })
+    }
+
+    /// Whether the mapping went from working to not working.
+    fn is_failure(&self) -> bool {
+        !matches!(
+            self.status,
+            MappingStatus::Active | MappingStatus::Healthy | MappingStatus::Starting
+        )
+    }
+
+    fn summary(&self) -> String {
+        format!("{}: {}", self.domain, self.status)
+    }
+
+    fn body(&self) -> String {
+        format!("Port {} (was {})", self.port, self.previous)
+    }
+
+    /// Environment for the hook command.
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("PORTMAP_DOMAIN", self.domain.clone()),
+            ("PORTMAP_PORT", self.port.to_string()),
+            ("PORTMAP_STATUS", self.status.key().to_string()),
+            ("PORTMAP_PREVIOUS_STATUS", self.previous.key().to_string()),
+        ];
+        if let MappingStatus::Unhealthy(code) = self.status {
+            env.push(("PORTMAP_HTTP_STATUS", code.to_string()));
+        }
+        env
+    }
+}
+
+/// Sends notifications to `org.freedesktop.Notifications`.
+pub struct DesktopNotifier {
+    connection: Connection,
+    /// Last notification per domain, replaced by the next one
+    ids: HashMap<String, u32>,
+}
+
+impl DesktopNotifier {
+    /// Connect to the session bus, or to the bus at `address`.
+    pub async fn connect(address: Option<&str>) -> Result<Self> {
+        let connection = match address {
+            Some(address) => zbus::connection::Builder::address(address)?.build().await?,
+            None => Connection::session().await?,
+        };
+        Ok(Self {
+            connection,
+            ids: HashMap::new(),
+        })
+    }
+
+    pub async fn notify(&mut self, event: &StatusEvent) -> Result<()> {
+        let replaces = self.ids.get(&event.domain).copied().unwrap_or(0);
+        // 2 = critical, 1 = normal
+        let urgency: u8 = if event.is_failure() { 2 } else { 1 };
+        let hints = HashMap::from([("urgency", Value::U8(urgency))]);
+        let reply = self
+            .connection
+            .call_method(
+                Some("org.freedesktop.Notifications"),
+                "/org/freedesktop/Notifications",
+                Some("org.freedesktop.Notifications"),
+                "Notify",
+                &(
+                    "portmap",
+                    replaces,
+                    "",
+                    event.summary(),
+                    event.body(),
+                    Vec::<&str>::new(),
+                    hints,
+                    -1i32,
+                ),
+            )
+            .await?;
+        let id: u32 = reply.body().deserialize()?;
+        self.ids.insert(event.domain.clone(), id);
+        Ok(())
+    }
+}
+
+/// Run the hook command for `event` without waiting for it.
+fn run_hook(hook: &[String], event: &StatusEvent) -> Result<()> {
+    let Some((program, args)) = hook.split_first() else {
+        return Ok(());
+    };
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .envs(event.env())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Reap it in the background
+    tokio::spawn(async move {
+        let _ = child.wait().await;
+    });
+    Ok(())
+}
+
+/// The hook to run, if any. It comes from the invoking user's config, so
+/// it's never run with root's privileges (when `privileges.drop` is off).
+pub fn runnable_hook(config: &NotificationsConfig, root: bool) -> Vec<String> {
+    if root && !config.hook.is_empty() {
+        eprintln!(
+            "Warning: not running notifications.hook as root; \
+             enable privileges.drop to run it as yourself"
+        );
+        return Vec::new();
+    }
+    config.hook.clone()
+}
+
+/// Deliver events from `events` until the channel closes.
+///
+/// Failures are ignored: no notification daemon, or a broken hook, must
+/// not get in the way of the TUI.
+pub fn spawn_event_handler(
+    config: NotificationsConfig,
+    mut events: mpsc::UnboundedReceiver<StatusEvent>,
+) {
+    tokio::spawn(async move {
+        let mut notifier = None;
+        if config.desktop {
+            notifier = DesktopNotifier::connect(None).await.ok();
+        }
+        while let Some(event) = events.recv().await {
+            if let Some(notifier) = &mut notifier {
+                let _ = notifier.notify(&event).await;
+            }
+            let _ = run_hook(&config.hook, &event);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn event(previous: MappingStatus, status: MappingStatus) -> StatusEvent {
+        StatusEvent::transition("api.localhost", 3000, &previous, &status).unwrap()
+    }
+
+    #[test]
+    fn test_transitions() {
+        let t = |a, b| StatusEvent::transition("api.localhost", 3000, &a, &b);
+        assert!(t(MappingStatus::Unknown, MappingStatus::Active).is_none());
+        assert!(t(MappingStatus::Active, MappingStatus::Active).is_none());
+        assert!(t(MappingStatus::Active, MappingStatus::PortUnreachable).is_some());
+        assert!(t(MappingStatus::PortUnreachable, MappingStatus::Active).is_some());
+        assert!(t(MappingStatus::Healthy, MappingStatus::Unhealthy(500)).is_some());
+    }
+
+    #[test]
+    fn test_hook_not_run_as_root() {
+        let config = NotificationsConfig {
+            hook: vec!["notify-me".to_string()],
+            ..NotificationsConfig::default()
+        };
+        assert_eq!(runnable_hook(&config, false), ["notify-me"]);
+        assert!(runnable_hook(&config, true).is_empty());
+    }
+
+    #[test]
+    fn test_hook_env() {
+        let env = event(MappingStatus::Healthy, MappingStatus::Unhealthy(502)).env();
+        let get = |k: &str| env.iter().find(|(key, _)| *key == k).map(|(_, v)| v.as_str());
+        assert_eq!(get("PORTMAP_DOMAIN"), Some("api.localhost"));
+        assert_eq!(get("PORTMAP_PORT"), Some("3000"));
+        assert_eq!(get("PORTMAP_STATUS"), Some("unhealthy"));
+        assert_eq!(get("PORTMAP_PREVIOUS_STATUS"), Some("healthy"));
+        assert_eq!(get("PORTMAP_HTTP_STATUS"), Some("502"));
+    }
+
+    #[tokio::test]
+    async fn test_hook_runs_with_event() {
+        let dir = TempDir::new().unwrap();
+        let out = dir.path().join("out");
+        let config = NotificationsConfig {
+            desktop: false,
+            hook: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!(
+                    "echo \"$PORTMAP_DOMAIN $PORTMAP_PORT $PORTMAP_STATUS\" > {}",
+                    out.display()
+                ),
+            ],
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        spawn_event_handler(config, rx);
+        tx.send(event(MappingStatus::Active, MappingStatus::PortUnreachable))
+            .unwrap();
+
+        for _ in 0..50 {
+            if let Ok(content) = std::fs::read_to_string(&out) {
+                if !content.is_empty() {
+                    assert_eq!(content, "api.localhost 3000 port-unreachable\n");
+                    return;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("hook did not run");
+    }
+
+    /// Stand-in for a notification daemon.
+    struct FakeNotifications {
+        received: Arc<Mutex<Vec<(u32, String, String)>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl FakeNotifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: &str,
+            replaces_id: u32,
+            _app_icon: &str,
+            summary: &str,
+            body: &str,
+            _actions: Vec<&str>,
+            _hints: HashMap<&str, Value<'_>>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut received = self.received.lock().unwrap();
+            received.push((replaces_id, summary.to_string(), body.to_string()));
+            // Same id as the one being replaced, else a new one
+            if replaces_id == 0 {
+                received.len() as u32
+            } else {
+                replaces_id
+            }
+        }
+    }
+
+    /// A private bus daemon, killed on drop.
+    struct Bus(std::process::Child);
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    /// Start a private session bus. `None` if dbus-daemon isn't installed.
+    fn start_bus(dir: &TempDir) -> Option<(Bus, String)> {
+        let config = dir.path().join("bus.conf");
+        std::fs::write(
+            &config,
+            format!(
+                r#"<busconfig>
+  <type>session</type>
+  <listen>unix:dir={}</listen>
+  <policy context="default">
+    <allow send_destination="*"/>
+    <allow receive_sender="*"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>"#,
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+        let mut child = std::process::Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .args(["--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let stdout = child.stdout.take().unwrap();
+        let bus = Bus(child);
+        let mut address = String::new();
+        BufReader::new(stdout).read_line(&mut address).ok()?;
+        Some((bus, address.trim().to_string()))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_desktop_notification_over_session_bus() {
+        let dir = TempDir::new().unwrap();
+        let Some((_bus, address)) = start_bus(&dir) else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let _daemon = zbus::connection::Builder::address(address.as_str())
+            .unwrap()
+            .name("org.freedesktop.Notifications")
+            .unwrap()
+            .serve_at(
+                "/org/freedesktop/Notifications",
+                FakeNotifications {
+                    received: received.clone(),
+                },
+            )
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let mut notifier = DesktopNotifier::connect(Some(&address)).await.unwrap();
+        notifier
+            .notify(&event(MappingStatus::Active, MappingStatus::PortUnreachable))
+            .await
+            .unwrap();
+        notifier
+            .notify(&event(MappingStatus::PortUnreachable, MappingStatus::Active))
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(
+            received,
+            vec![
+                (
+                    0,
+                    "api.localhost: Port Unreachable".to_string(),
+                    "Port 3000 (was Active)".to_string()
+                ),
+                // The second notification replaces the first
+                (
+                    1,
+                    "api.localhost: Active".to_string(),
+                    "Port 3000 (was Port Unreachable)".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::config::{Config, ResolverBackend};
+use crate::hosts::dnsmasq::DnsmasqBackend;
+use crate::hosts::manager::HostsManager;
+use crate::hosts::parser::UnmanagedEntry;
+use crate::hosts::resolved::ResolvedBackend;
+use anyhow::Result;
+use std::sync::Arc;
+
+/// A way of making mapped domains resolve to loopback.
+///
+/// Implementations must be safe to call from the panic hook and signal
+/// handlers, so `restore_all` may not rely on any async runtime.
+pub trait HostsBackend: Send + Sync {
+    /// Human-readable name of what this backend edits, for messages.
+    fn describe(&self) -> String;
+
+    /// Make `domain` resolve locally. Returns false if it already does.
+    fn add_entry(&self, domain: &str) -> Result<bool>;
+
+    /// Stop resolving `domain`. Returns false if it wasn't mapped.
+    fn remove_entry(&self, domain: &str) -> Result<bool>;
+
+    /// Move the entry for `old` to `new`. Returns false if `new` is
+    /// already mapped or `old` isn't, in which case nothing changes.
+    fn rename_entry(&self, old: &str, new: &str) -> Result<bool> {
+        if !self.add_entry(new)? {
+            return Ok(false);
+        }
+        if !self.remove_entry(old)? {
+            self.remove_entry(new)?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Undo everything portmap has changed (for cleanup).
+    fn restore_all(&self) -> Result<()>;
+
+    /// Existing entries this backend doesn't own that map `domain` elsewhere.
+    fn conflicts(&self, _domain: &str) -> Result<Vec<UnmanagedEntry>> {
+        Ok(Vec::new())
+    }
+
+    /// Disable the conflicting entries for `domain` for the session.
+    fn disable_conflicts(&self, _domain: &str) -> Result<Vec<UnmanagedEntry>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Build the backend selected in the config.
+pub fn from_config(config: &Config) -> Result<Arc<dyn HostsBackend>> {
+    let ip_family = config.hosts.ip_family;
+    Ok(match config.hosts.backend {
+        ResolverBackend::HostsFile => Arc::new(HostsManager::new(ip_family)),
+        ResolverBackend::Dnsmasq => {
+            Arc::new(DnsmasqBackend::new(config.hosts.dnsmasq.clone(), ip_family))
+        }
+        ResolverBackend::Resolved => {
+            let server = config.hosts.resolved.server(&config.dns)?;
+            Arc::new(ResolvedBackend::new(config.hosts.resolved.clone(), server))
+        }
+        ResolverBackend::None => Arc::new(NoopBackend),
+    })
+}
+
+/// Leaves system name resolution alone.
+pub struct NoopBackend;
+
+impl HostsBackend for NoopBackend {
+    fn describe(&self) -> String {
+        "no resolver backend".to_string()
+    }
+
+    fn add_entry(&self, _domain: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn remove_entry(&self, _domain: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn restore_all(&self) -> Result<()> {
+        Ok(())
+    }
+}
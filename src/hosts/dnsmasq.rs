@@ -0,0 +1,187 @@
+use crate::config::{DnsmasqConfig, IpFamily};
+use crate::hosts::backend::HostsBackend;
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+use std::process::Command;
+
+const FILE_NAME: &str = "portmap.conf";
+const HEADER: &str = "# Managed by portmap — removed automatically on exit";
+
+/// Writes `address=/domain/ip` lines to a dnsmasq drop-in directory
+/// (plain dnsmasq or NetworkManager's `dnsmasq.d`) and reloads dnsmasq.
+pub struct DnsmasqBackend {
+    path: PathBuf,
+    reload: Vec<String>,
+    ips: &'static [&'static str],
+}
+
+impl DnsmasqBackend {
+    pub fn new(config: DnsmasqConfig, ip_family: IpFamily) -> Self {
+        Self {
+            path: config.dir.join(FILE_NAME),
+            reload: config.reload,
+            ips: ip_family.loopback_ips(),
+        }
+    }
+
+    /// Domains currently present in the drop-in, in file order.
+    fn read_domains(&self) -> Result<Vec<String>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        };
+        let mut domains: Vec<String> = Vec::new();
+        for line in content.lines() {
+            let Some(rest) = line.trim().strip_prefix("address=/") else {
+                continue;
+            };
+            let Some(domain) = rest.split('/').next() else {
+                continue;
+            };
+            if !domains.iter().any(|d| d == domain) {
+                domains.push(domain.to_string());
+            }
+        }
+        Ok(domains)
+    }
+
+    fn write_domains(&self, domains: &[String]) -> Result<()> {
+        if domains.is_empty() {
+            match std::fs::remove_file(&self.path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to remove {}", self.path.display()))
+                }
+            }
+        } else {
+            let mut content = String::from(HEADER);
+            content.push('\n');
+            for domain in domains {
+                for ip in self.ips {
+                    content.push_str(&format!("address=/{}/{}\n", domain, ip));
+                }
+            }
+            std::fs::write(&self.path, content)
+                .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        }
+        self.reload()
+    }
+
+    fn reload(&self) -> Result<()> {
+        let Some((program, args)) = self.reload.split_first() else {
+            return Ok(());
+        };
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to run {}", program))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{} failed: {}",
+                self.reload.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl HostsBackend for DnsmasqBackend {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn add_entry(&self, domain: &str) -> Result<bool> {
+        let mut domains = self.read_domains()?;
+        if domains.iter().any(|d| d == domain) {
+            return Ok(false);
+        }
+        domains.push(domain.to_string());
+        self.write_domains(&domains)?;
+        Ok(true)
+    }
+
+    fn remove_entry(&self, domain: &str) -> Result<bool> {
+        let mut domains = self.read_domains()?;
+        let len_before = domains.len();
+        domains.retain(|d| d != domain);
+        if domains.len() == len_before {
+            return Ok(false);
+        }
+        self.write_domains(&domains)?;
+        Ok(true)
+    }
+
+    fn restore_all(&self) -> Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        self.write_domains(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn backend(dir: &TempDir, reload: Vec<String>) -> DnsmasqBackend {
+        let config = DnsmasqConfig {
+            dir: dir.path().to_path_buf(),
+            reload,
+        };
+        DnsmasqBackend::new(config, IpFamily::Dual)
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let dir = TempDir::new().unwrap();
+        let backend = backend(&dir, Vec::new());
+        let path = dir.path().join(FILE_NAME);
+
+        assert!(backend.add_entry("api.localhost").unwrap());
+        assert!(!backend.add_entry("api.localhost").unwrap());
+        assert!(backend.add_entry("web.localhost").unwrap());
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("address=/api.localhost/127.0.0.1\n"));
+        assert!(content.contains("address=/api.localhost/::1\n"));
+        assert!(content.contains("address=/web.localhost/127.0.0.1\n"));
+
+        assert!(backend.remove_entry("api.localhost").unwrap());
+        assert!(!backend.remove_entry("api.localhost").unwrap());
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("api.localhost"));
+
+        backend.restore_all().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_reload_runs_after_each_change() {
+        let dir = TempDir::new().unwrap();
+        let log = dir.path().join("reload.log");
+        let reload = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("echo reload >> {}", log.display()),
+        ];
+        let backend = backend(&dir, reload);
+
+        backend.add_entry("api.localhost").unwrap();
+        backend.remove_entry("api.localhost").unwrap();
+        let runs = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(runs.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_failed_reload_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let backend = backend(&dir, vec!["false".to_string()]);
+        assert!(backend.add_entry("api.localhost").is_err());
+    }
+}
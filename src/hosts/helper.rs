@@ -0,0 +1,513 @@
+//! Privileged hosts helper.
+//!
+//! When portmap drops root, a small child process keeps it and performs
+//! hosts mutations on the unprivileged process's behalf. The protocol is
+//! one line per request over the child's stdin/stdout, and deliberately
+//! narrow: `add <domain>`, `remove <domain>`, `rename <old> <new>` and
+//! `restore`.
+//!
+//! The first line names the backend to run (`setup <backend> <ip-family>
+//! <comment-out-conflicts> <dns-listen|->`). How that backend is run —
+//! dnsmasq's directory and reload command, the `resolvectl` binary and
+//! link — comes from the root-owned system config, never from the invoking
+//! user's. Only the address of portmap's own DNS responder is passed along,
+//! as the resolved backend's default server.
+
+use crate::config::{Config, IpFamily, ResolverBackend};
+use crate::hosts::backend::{self, HostsBackend};
+use crate::hosts::parser::{HostsFile, UnmanagedEntry};
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+/// Hidden command-line flag that starts the helper.
+pub const HELPER_FLAG: &str = "--hosts-helper";
+
+/// A request the unprivileged process may send.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HelperCommand {
+    Add(String),
+    Remove(String),
+    Rename(String, String),
+    Restore,
+}
+
+impl HelperCommand {
+    /// Parse one request line, rejecting anything outside the protocol.
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut parts = line.trim_end().splitn(2, ' ');
+        let command = parts.next().unwrap_or("");
+        let arg = parts.next();
+        match (command, arg) {
+            ("add", Some(domain)) => Ok(HelperCommand::Add(valid_domain(domain)?)),
+            ("remove", Some(domain)) => Ok(HelperCommand::Remove(valid_domain(domain)?)),
+            ("rename", Some(domains)) => {
+                let (old, new) = domains.split_once(' ').context("rename needs two domains")?;
+                Ok(HelperCommand::Rename(valid_domain(old)?, valid_domain(new)?))
+            }
+            ("restore", None) => Ok(HelperCommand::Restore),
+            _ => bail!("unknown request"),
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            HelperCommand::Add(domain) => format!("add {}\n", domain),
+            HelperCommand::Remove(domain) => format!("remove {}\n", domain),
+            HelperCommand::Rename(old, new) => format!("rename {} {}\n", old, new),
+            HelperCommand::Restore => "restore\n".to_string(),
+        }
+    }
+}
+
+/// The one thing the unprivileged side chooses about the helper: which
+/// backend it runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HelperSetup {
+    pub backend: ResolverBackend,
+    pub ip_family: IpFamily,
+    pub comment_out_conflicts: bool,
+    /// Where portmap's DNS responder listens, if it's enabled
+    pub dns: Option<SocketAddr>,
+}
+
+impl HelperSetup {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            backend: config.hosts.backend,
+            ip_family: config.hosts.ip_family,
+            comment_out_conflicts: config.hosts.comment_out_conflicts,
+            dns: config.dns.enabled.then_some(config.dns.listen),
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self> {
+        let parts: Vec<&str> = line.trim_end().split(' ').collect();
+        let ["setup", backend, ip_family, conflicts, dns] = parts[..] else {
+            bail!("expected setup line");
+        };
+        let backend = match backend {
+            "hosts-file" => ResolverBackend::HostsFile,
+            "dnsmasq" => ResolverBackend::Dnsmasq,
+            "resolved" => ResolverBackend::Resolved,
+            "none" => ResolverBackend::None,
+            _ => bail!("unknown backend"),
+        };
+        let ip_family = match ip_family {
+            "v4" => IpFamily::V4,
+            "v6" => IpFamily::V6,
+            "dual" => IpFamily::Dual,
+            _ => bail!("unknown ip family"),
+        };
+        let comment_out_conflicts = match conflicts {
+            "true" => true,
+            "false" => false,
+            _ => bail!("invalid conflict policy"),
+        };
+        let dns = match dns {
+            "-" => None,
+            addr => Some(addr.parse().map_err(|_| anyhow!("invalid DNS address"))?),
+        };
+        Ok(Self {
+            backend,
+            ip_family,
+            comment_out_conflicts,
+            dns,
+        })
+    }
+
+    fn encode(&self) -> String {
+        let backend = match self.backend {
+            ResolverBackend::HostsFile => "hosts-file",
+            ResolverBackend::Dnsmasq => "dnsmasq",
+            ResolverBackend::Resolved => "resolved",
+            ResolverBackend::None => "none",
+        };
+        let ip_family = match self.ip_family {
+            IpFamily::V4 => "v4",
+            IpFamily::V6 => "v6",
+            IpFamily::Dual => "dual",
+        };
+        let dns = self.dns.map_or("-".to_string(), |addr| addr.to_string());
+        format!(
+            "setup {} {} {} {}\n",
+            backend, ip_family, self.comment_out_conflicts, dns
+        )
+    }
+
+    /// The backend the helper runs: this choice, configured from the
+    /// system config.
+    pub fn backend(&self) -> Result<Arc<dyn HostsBackend>> {
+        backend::from_config(&self.apply(Config::load_system()?))
+    }
+
+    /// `system` with this choice of backend; its commands and paths stay.
+    fn apply(&self, mut config: Config) -> Config {
+        config.hosts.backend = self.backend;
+        config.hosts.ip_family = self.ip_family;
+        config.dns.enabled = self.dns.is_some();
+        if let Some(addr) = self.dns {
+            config.dns.listen = addr;
+        }
+        config
+    }
+}
+
+/// Only plain hostnames may reach the privileged side.
+fn valid_domain(domain: &str) -> Result<String> {
+    let ok = !domain.is_empty()
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        });
+    if !ok {
+        bail!("invalid domain");
+    }
+    Ok(domain.to_string())
+}
+
+/// Serve requests from `input` until it closes, then restore everything.
+pub fn serve<R: BufRead, W: Write>(
+    input: R,
+    mut output: W,
+    hosts: &dyn HostsBackend,
+    comment_out_conflicts: bool,
+) -> Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let response = match HelperCommand::parse(&line) {
+            Ok(HelperCommand::Add(domain)) => hosts.add_entry(&domain).map(|added| {
+                // The unprivileged side can't edit the file, so the
+                // configured conflict policy is applied here
+                if added && comment_out_conflicts {
+                    let _ = hosts.disable_conflicts(&domain);
+                }
+                format!("ok {}", added)
+            }),
+            Ok(HelperCommand::Remove(domain)) => {
+                hosts.remove_entry(&domain).map(|removed| format!("ok {}", removed))
+            }
+            Ok(HelperCommand::Rename(old, new)) => hosts.rename_entry(&old, &new).map(|renamed| {
+                if renamed && comment_out_conflicts {
+                    let _ = hosts.disable_conflicts(&new);
+                }
+                format!("ok {}", renamed)
+            }),
+            Ok(HelperCommand::Restore) => hosts.restore_all().map(|()| "ok".to_string()),
+            Err(e) => Err(e),
+        };
+        let response = response.unwrap_or_else(|e| {
+            // Keep the reply on one line
+            format!("err {}", e.to_string().replace('\n', " "))
+        });
+        writeln!(output, "{}", response)?;
+        output.flush()?;
+    }
+
+    // The parent is gone (quit, crash or SIGKILL) — clean up after it
+    hosts.restore_all()
+}
+
+/// Entry point of `portmap --hosts-helper`.
+pub fn run_helper() -> Result<()> {
+    // Terminal signals hit the whole process group; stay alive until the
+    // parent has sent its final `restore` and closed the pipe
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+        libc::signal(libc::SIGTERM, libc::SIG_IGN);
+        libc::signal(libc::SIGHUP, libc::SIG_IGN);
+    }
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let setup = HelperSetup::parse(&line)?;
+    let hosts = setup.backend()?;
+    serve(
+        input,
+        std::io::stdout(),
+        hosts.as_ref(),
+        setup.comment_out_conflicts,
+    )
+}
+
+struct HelperIo {
+    writer: Box<dyn Write + Send>,
+    reader: Box<dyn BufRead + Send>,
+}
+
+/// The unprivileged side: a `HostsBackend` that forwards to the helper.
+pub struct HelperClient {
+    io: Mutex<HelperIo>,
+    describe: String,
+    /// Hosts file to read for conflict reports (it's world-readable)
+    hosts_path: Option<PathBuf>,
+    ips: &'static [&'static str],
+    _child: Option<Child>,
+}
+
+impl HelperClient {
+    /// Start `portmap --hosts-helper` as a child of the (still root) process.
+    pub fn spawn(setup: HelperSetup, describe: String, hosts_path: Option<PathBuf>) -> Result<Self> {
+        let exe = std::env::current_exe()?;
+        let mut child = Command::new(exe)
+            .arg(HELPER_FLAG)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to start the privileged hosts helper")?;
+        let mut writer = child.stdin.take().context("helper has no stdin")?;
+        writer.write_all(setup.encode().as_bytes())?;
+        let reader = child.stdout.take().context("helper has no stdout")?;
+        let mut client = Self::connect(
+            Box::new(writer),
+            Box::new(BufReader::new(reader)),
+            describe,
+            hosts_path,
+            setup.ip_family.loopback_ips(),
+        );
+        client._child = Some(child);
+        Ok(client)
+    }
+
+    fn connect(
+        writer: Box<dyn Write + Send>,
+        reader: Box<dyn BufRead + Send>,
+        describe: String,
+        hosts_path: Option<PathBuf>,
+        ips: &'static [&'static str],
+    ) -> Self {
+        Self {
+            io: Mutex::new(HelperIo { writer, reader }),
+            describe,
+            hosts_path,
+            ips,
+            _child: None,
+        }
+    }
+
+    fn request(&self, command: HelperCommand) -> Result<Option<bool>> {
+        let mut io = self.io.lock().unwrap_or_else(|e| e.into_inner());
+        io.writer.write_all(command.encode().as_bytes())?;
+        io.writer.flush()?;
+        let mut line = String::new();
+        if io.reader.read_line(&mut line)? == 0 {
+            bail!("hosts helper exited");
+        }
+        match line.trim_end() {
+            "ok" => Ok(None),
+            "ok true" => Ok(Some(true)),
+            "ok false" => Ok(Some(false)),
+            other => Err(anyhow!("{}", other.strip_prefix("err ").unwrap_or(other))),
+        }
+    }
+
+    fn read_hosts(&self) -> Result<Option<HostsFile>> {
+        let Some(path) = &self.hosts_path else {
+            return Ok(None);
+        };
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some(HostsFile::parse(&content)))
+    }
+}
+
+impl HostsBackend for HelperClient {
+    fn describe(&self) -> String {
+        self.describe.clone()
+    }
+
+    fn add_entry(&self, domain: &str) -> Result<bool> {
+        Ok(self.request(HelperCommand::Add(domain.to_string()))? == Some(true))
+    }
+
+    fn remove_entry(&self, domain: &str) -> Result<bool> {
+        Ok(self.request(HelperCommand::Remove(domain.to_string()))? == Some(true))
+    }
+
+    /// One request, so the helper renames in a single write.
+    fn rename_entry(&self, old: &str, new: &str) -> Result<bool> {
+        let request = HelperCommand::Rename(old.to_string(), new.to_string());
+        Ok(self.request(request)? == Some(true))
+    }
+
+    fn restore_all(&self) -> Result<()> {
+        self.request(HelperCommand::Restore).map(|_| ())
+    }
+
+    fn conflicts(&self, domain: &str) -> Result<Vec<UnmanagedEntry>> {
+        Ok(self
+            .read_hosts()?
+            .map(|hosts| hosts.conflicts(domain, self.ips))
+            .unwrap_or_default())
+    }
+
+    /// The helper already commented them out while adding; report which.
+    fn disable_conflicts(&self, domain: &str) -> Result<Vec<UnmanagedEntry>> {
+        Ok(self
+            .read_hosts()?
+            .map(|hosts| {
+                hosts
+                    .unmanaged_entries()
+                    .into_iter()
+                    .filter(|e| e.disabled && e.names.iter().any(|n| n == domain))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hosts::manager::HostsManager;
+    use std::os::unix::net::UnixStream;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            HelperCommand::parse("add api.localhost").unwrap(),
+            HelperCommand::Add("api.localhost".to_string())
+        );
+        assert_eq!(
+            HelperCommand::parse("remove api.localhost\n").unwrap(),
+            HelperCommand::Remove("api.localhost".to_string())
+        );
+        assert_eq!(
+            HelperCommand::parse("rename a.localhost b.localhost").unwrap(),
+            HelperCommand::Rename("a.localhost".to_string(), "b.localhost".to_string())
+        );
+        assert_eq!(HelperCommand::parse("restore").unwrap(), HelperCommand::Restore);
+    }
+
+    #[test]
+    fn test_parse_rejects_anything_else() {
+        assert!(HelperCommand::parse("write /etc/passwd").is_err());
+        assert!(HelperCommand::parse("add").is_err());
+        assert!(HelperCommand::parse("restore now").is_err());
+        assert!(HelperCommand::parse("add evil.localhost\n127.0.0.1 bank.com").is_err());
+        assert!(HelperCommand::parse("add ../etc").is_err());
+        assert!(HelperCommand::parse("add -x.localhost").is_err());
+        assert!(HelperCommand::parse("add a b").is_err());
+        assert!(HelperCommand::parse("rename a.localhost").is_err());
+        assert!(HelperCommand::parse("rename a.localhost b c").is_err());
+        assert!(HelperCommand::parse("rename a.localhost ../etc").is_err());
+    }
+
+    #[test]
+    fn test_setup_round_trip() {
+        let setup = HelperSetup {
+            backend: ResolverBackend::Dnsmasq,
+            ip_family: IpFamily::V4,
+            comment_out_conflicts: true,
+            dns: None,
+        };
+        assert_eq!(setup.encode(), "setup dnsmasq v4 true -\n");
+        assert_eq!(HelperSetup::parse(&setup.encode()).unwrap(), setup);
+        let setup = HelperSetup {
+            backend: ResolverBackend::Resolved,
+            dns: Some("[::1]:1053".parse().unwrap()),
+            ..setup
+        };
+        assert_eq!(setup.encode(), "setup resolved v4 true [::1]:1053\n");
+        assert_eq!(HelperSetup::parse(&setup.encode()).unwrap(), setup);
+
+        // Only the backend kind crosses the pipe, never how to run it
+        assert!(HelperSetup::parse("setup dnsmasq v4 true - /tmp").is_err());
+        assert!(HelperSetup::parse("setup dnsmasq v4 true /tmp").is_err());
+        assert!(HelperSetup::parse("setup sh v4 true -").is_err());
+        assert!(HelperSetup::parse("add api.localhost").is_err());
+    }
+
+    #[test]
+    fn test_root_backend_ignores_user_commands() {
+        let user = Config::parse(
+            "[hosts]\nbackend = \"dnsmasq\"\ncomment_out_conflicts = true\n\
+             [hosts.dnsmasq]\ndir = \"/tmp/mine\"\nreload = [\"sh\", \"-c\", \"id\"]\n\
+             [hosts.resolved]\nresolvectl = \"/tmp/mine/resolvectl\"\n",
+        )
+        .unwrap();
+        let setup = HelperSetup::from_config(&user);
+        let config = setup.apply(Config::default());
+
+        assert_eq!(config.hosts.backend, ResolverBackend::Dnsmasq);
+        let defaults = Config::default().hosts;
+        assert_eq!(config.hosts.dnsmasq.reload, defaults.dnsmasq.reload);
+        assert_eq!(config.hosts.dnsmasq.dir, defaults.dnsmasq.dir);
+        assert_eq!(config.hosts.resolved.resolvectl, defaults.resolved.resolvectl);
+    }
+
+    #[test]
+    fn test_serve_restores_on_eof() {
+        let file = NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "127.0.0.1\tlocalhost\n").unwrap();
+        let manager = HostsManager::with_path(file.path().to_path_buf());
+
+        let input = "add api.localhost\nadd api.localhost\nbogus\nremove web.localhost\n";
+        let mut output = Vec::new();
+        // The parent never sends `restore`, as if it had been killed
+        serve(input.as_bytes(), &mut output, &manager, false).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            vec!["ok true", "ok false", "err unknown request", "ok false"]
+        );
+        let content = std::fs::read_to_string(file.path()).unwrap();
+        assert_eq!(content, "127.0.0.1\tlocalhost\n");
+    }
+
+    #[test]
+    fn test_client_round_trip() {
+        let file = NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "10.0.0.5\tapi.localhost\n").unwrap();
+        let path = file.path().to_path_buf();
+
+        let (client_side, helper_side) = UnixStream::pair().unwrap();
+        let helper_path = path.clone();
+        let helper = std::thread::spawn(move || {
+            let manager = HostsManager::with_path(helper_path);
+            let reader = BufReader::new(helper_side.try_clone().unwrap());
+            serve(reader, helper_side, &manager, true).unwrap();
+        });
+
+        let client = HelperClient::connect(
+            Box::new(client_side.try_clone().unwrap()),
+            Box::new(BufReader::new(client_side)),
+            "test".to_string(),
+            Some(path.clone()),
+            &["127.0.0.1", "::1"],
+        );
+        assert!(client.add_entry("api.localhost").unwrap());
+        assert!(!client.add_entry("api.localhost").unwrap());
+        assert!(client.conflicts("api.localhost").unwrap().is_empty());
+        assert_eq!(client.disable_conflicts("api.localhost").unwrap().len(), 1);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("127.0.0.1\tapi.localhost"));
+        assert!(content.contains("#portmap-disabled 10.0.0.5\tapi.localhost"));
+
+        assert!(client.rename_entry("api.localhost", "web.localhost").unwrap());
+        assert!(!client.rename_entry("api.localhost", "app.localhost").unwrap());
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("127.0.0.1\tweb.localhost"));
+        assert!(!content.contains("app.localhost"));
+        assert!(content.starts_with("10.0.0.5\tapi.localhost\n"));
+
+        client.restore_all().unwrap();
+        drop(client);
+        helper.join().unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "10.0.0.5\tapi.localhost\n");
+    }
+}
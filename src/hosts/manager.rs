@@ -1,23 +1,34 @@
-use crate::hosts::parser::HostsFile;
+use crate::config::IpFamily;
+use crate::hosts::backend::HostsBackend;
+use crate::hosts::parser::{HostsFile, UnmanagedEntry};
 use anyhow::{Context, Result};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+
+/// The system hosts file.
+pub const HOSTS_PATH: &str = "/etc/hosts";
 
 /// Manages the /etc/hosts file with portmap sentinel blocks.
 pub struct HostsManager {
     path: PathBuf,
+    /// Loopback addresses written for every domain
+    ips: &'static [&'static str],
 }
 
 impl HostsManager {
-    pub fn new() -> Self {
+    pub fn new(ip_family: IpFamily) -> Self {
         Self {
-            path: PathBuf::from("/etc/hosts"),
+            path: PathBuf::from(HOSTS_PATH),
+            ips: ip_family.loopback_ips(),
         }
     }
 
     /// Create a manager with a custom path (for testing).
     #[cfg(test)]
     pub fn with_path(path: PathBuf) -> Self {
-        Self { path }
+        Self {
+            path,
+            ips: IpFamily::Dual.loopback_ips(),
+        }
     }
 
     fn read(&self) -> Result<String> {
@@ -30,52 +41,85 @@ impl HostsManager {
             .with_context(|| format!("Failed to write {}", self.path.display()))
     }
 
-    /// Add a domain → localhost mapping to /etc/hosts.
-    pub fn add_entry(&self, domain: &str) -> Result<bool> {
+    #[cfg(test)]
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+impl HostsBackend for HostsManager {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// Add a domain → loopback mapping (one line per configured IP) to /etc/hosts.
+    fn add_entry(&self, domain: &str) -> Result<bool> {
         let content = self.read()?;
         let mut hosts = HostsFile::parse(&content);
-        if !hosts.add_entry(domain, "127.0.0.1") {
+        if !hosts.add_entry(domain, self.ips) {
             return Ok(false);
         }
         self.write(&hosts.serialize())?;
         Ok(true)
     }
 
-    /// Remove a domain mapping from /etc/hosts.
-    pub fn remove_entry(&self, domain: &str) -> Result<bool> {
+    /// Remove a domain mapping from /etc/hosts, restoring any lines that
+    /// were commented out because they conflicted with it.
+    fn remove_entry(&self, domain: &str) -> Result<bool> {
         let content = self.read()?;
         let mut hosts = HostsFile::parse(&content);
         if !hosts.remove_entry(domain) {
             return Ok(false);
         }
+        hosts.enable_conflicts(domain);
         self.write(&hosts.serialize())?;
         Ok(true)
     }
 
-    /// Remove all portmap-managed entries from /etc/hosts.
-    pub fn restore_all(&self) -> Result<()> {
+    /// Rename a domain in a single write, so there is no moment where
+    /// neither name resolves.
+    fn rename_entry(&self, old: &str, new: &str) -> Result<bool> {
         let content = self.read()?;
         let mut hosts = HostsFile::parse(&content);
-        hosts.remove_all();
+        if hosts.entries.iter().any(|e| e.domain == new) || !hosts.remove_entry(old) {
+            return Ok(false);
+        }
+        hosts.add_entry(new, self.ips);
+        // After adding, so a line naming both stays disabled
+        hosts.enable_conflicts(old);
         self.write(&hosts.serialize())?;
-        Ok(())
+        Ok(true)
     }
 
-    #[cfg(test)]
-    pub fn path(&self) -> &Path {
-        &self.path
+    /// Entries outside the managed block that map `domain` elsewhere.
+    fn conflicts(&self, domain: &str) -> Result<Vec<UnmanagedEntry>> {
+        let content = self.read()?;
+        Ok(HostsFile::parse(&content).conflicts(domain, self.ips))
     }
-}
 
-/// Synchronous cleanup function for use in panic hooks and signal handlers.
-/// Reads /etc/hosts and removes the sentinel block.
-pub fn sync_cleanup(path: &Path) {
-    let Ok(content) = std::fs::read_to_string(path) else {
-        return;
-    };
-    let mut hosts = HostsFile::parse(&content);
-    hosts.remove_all();
-    let _ = std::fs::write(path, hosts.serialize());
+    /// Comment out conflicting entries for `domain` until it is removed
+    /// or portmap exits. Returns the entries that were disabled.
+    fn disable_conflicts(&self, domain: &str) -> Result<Vec<UnmanagedEntry>> {
+        let content = self.read()?;
+        let mut hosts = HostsFile::parse(&content);
+        let conflicts = hosts.conflicts(domain, self.ips);
+        if conflicts.is_empty() {
+            return Ok(conflicts);
+        }
+        let lines: Vec<usize> = conflicts.iter().map(|c| c.line).collect();
+        hosts.disable_lines(&lines);
+        self.write(&hosts.serialize())?;
+        Ok(conflicts)
+    }
+
+    /// Remove all portmap-managed entries from /etc/hosts.
+    fn restore_all(&self) -> Result<()> {
+        let content = self.read()?;
+        let mut hosts = HostsFile::parse(&content);
+        hosts.remove_all();
+        self.write(&hosts.serialize())?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -99,7 +143,8 @@ mod tests {
         // Add entry
         assert!(manager.add_entry("test.localhost").unwrap());
         let content = std::fs::read_to_string(manager.path()).unwrap();
-        assert!(content.contains("test.localhost"));
+        assert!(content.contains("127.0.0.1\ttest.localhost"));
+        assert!(content.contains("::1\ttest.localhost"));
         assert!(content.contains("portmap-start"));
 
         // Duplicate returns false
@@ -115,6 +160,31 @@ mod tests {
         assert!(content.contains("127.0.0.1\tlocalhost"));
     }
 
+    #[test]
+    fn test_rename() {
+        let (_file, manager) = temp_hosts("127.0.0.1\tlocalhost\n");
+        manager.add_entry("a.localhost").unwrap();
+        manager.add_entry("b.localhost").unwrap();
+
+        // Can't rename onto an existing mapping
+        assert!(!manager.rename_entry("a.localhost", "b.localhost").unwrap());
+
+        assert!(manager.rename_entry("a.localhost", "c.localhost").unwrap());
+        let content = std::fs::read_to_string(manager.path()).unwrap();
+        assert!(!content.contains("a.localhost"));
+        assert!(content.contains("127.0.0.1\tc.localhost"));
+        assert!(content.contains("b.localhost"));
+    }
+
+    #[test]
+    fn test_rename_missing_entry() {
+        let original = "127.0.0.1\tlocalhost\n";
+        let (_file, manager) = temp_hosts(original);
+        assert!(!manager.rename_entry("a.localhost", "c.localhost").unwrap());
+        let content = std::fs::read_to_string(manager.path()).unwrap();
+        assert_eq!(content, original);
+    }
+
     #[test]
     fn test_restore_all() {
         let (_file, manager) = temp_hosts("127.0.0.1\tlocalhost\n");
@@ -126,4 +196,23 @@ mod tests {
         assert!(!content.contains("a.localhost"));
         assert!(content.contains("127.0.0.1\tlocalhost"));
     }
+
+    #[test]
+    fn test_conflicts_disabled_for_session() {
+        let original = "127.0.0.1\tlocalhost\n10.0.0.5\tapi.localhost\n";
+        let (_file, manager) = temp_hosts(original);
+        manager.add_entry("api.localhost").unwrap();
+
+        let conflicts = manager.conflicts("api.localhost").unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].line, 2);
+
+        let disabled = manager.disable_conflicts("api.localhost").unwrap();
+        assert_eq!(disabled, conflicts);
+        assert!(manager.conflicts("api.localhost").unwrap().is_empty());
+
+        manager.remove_entry("api.localhost").unwrap();
+        let content = std::fs::read_to_string(manager.path()).unwrap();
+        assert_eq!(content, original);
+    }
 }
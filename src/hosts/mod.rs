@@ -1,2 +1,7 @@
+pub mod backend;
+pub mod dnsmasq;
+pub mod helper;
 pub mod manager;
 pub mod parser;
+pub mod resolved;
+pub mod watcher;
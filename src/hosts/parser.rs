@@ -1,5 +1,9 @@
+use std::net::IpAddr;
+
 const SENTINEL_START: &str = "# portmap-start (DO NOT EDIT - managed by portmap)";
 const SENTINEL_END: &str = "# portmap-end";
+/// Prefix for unmanaged lines portmap has commented out for the session.
+const DISABLED_PREFIX: &str = "#portmap-disabled ";
 
 /// Represents the parsed state of /etc/hosts with portmap's managed block.
 #[derive(Debug, Clone)]
@@ -10,20 +14,55 @@ pub struct HostsFile {
     pub entries: Vec<HostEntry>,
     /// Lines after the sentinel block.
     pub after: Vec<String>,
+    /// Whether the original content ended with a newline.
+    pub trailing_newline: bool,
 }
 
+/// A managed domain and every address it is written with (one line per IP).
 #[derive(Debug, Clone, PartialEq)]
 pub struct HostEntry {
-    pub ip: String,
+    pub ips: Vec<String>,
     pub domain: String,
 }
 
+/// A host line outside the sentinel block that portmap doesn't own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnmanagedEntry {
+    /// 1-based line number in the file
+    pub line: usize,
+    pub ip: String,
+    pub names: Vec<String>,
+    /// Commented out by portmap for the current session
+    pub disabled: bool,
+}
+
+/// Parse a raw hosts line into `(ip, names)`, ignoring comments and
+/// anything whose first field isn't an IP address.
+fn parse_host_line(line: &str) -> Option<(String, Vec<String>)> {
+    let content = line.split('#').next().unwrap_or("");
+    let mut fields = content.split_whitespace();
+    let ip = fields.next()?;
+    ip.parse::<std::net::IpAddr>().ok()?;
+    let names: Vec<String> = fields.map(|n| n.to_string()).collect();
+    if names.is_empty() {
+        return None;
+    }
+    Some((ip.to_string(), names))
+}
+
 impl HostsFile {
     /// Parse a hosts file content string into structured form.
+    ///
+    /// Lines outside the sentinel block are kept verbatim (including `\r`),
+    /// so an unchanged file serializes back byte-for-byte.
     pub fn parse(content: &str) -> Self {
-        let lines: Vec<&str> = content.lines().collect();
+        let trailing_newline = content.is_empty() || content.ends_with('\n');
+        let mut lines: Vec<&str> = content.split('\n').collect();
+        if content.is_empty() || trailing_newline {
+            lines.pop();
+        }
         let mut before = Vec::new();
-        let mut entries = Vec::new();
+        let mut entries: Vec<HostEntry> = Vec::new();
         let mut after = Vec::new();
 
         let start_idx = lines.iter().position(|l| l.trim() == SENTINEL_START);
@@ -43,10 +82,19 @@ impl HostsFile {
                     }
                     let parts: Vec<&str> = trimmed.split_whitespace().collect();
                     if parts.len() >= 2 {
-                        entries.push(HostEntry {
-                            ip: parts[0].to_string(),
-                            domain: parts[1].to_string(),
-                        });
+                        // Group the per-IP lines of a domain into one entry
+                        let ip = parts[0].to_string();
+                        match entries.iter_mut().find(|e| e.domain == parts[1]) {
+                            Some(entry) => {
+                                if !entry.ips.contains(&ip) {
+                                    entry.ips.push(ip);
+                                }
+                            }
+                            None => entries.push(HostEntry {
+                                ips: vec![ip],
+                                domain: parts[1].to_string(),
+                            }),
+                        }
                     }
                 }
                 // Lines after sentinel block
@@ -66,6 +114,7 @@ impl HostsFile {
             before,
             entries,
             after,
+            trailing_newline,
         }
     }
 
@@ -82,8 +131,10 @@ impl HostsFile {
             result.push_str(SENTINEL_START);
             result.push('\n');
             for entry in &self.entries {
-                result.push_str(&format!("{}\t{}", entry.ip, entry.domain));
-                result.push('\n');
+                for ip in &entry.ips {
+                    result.push_str(&format!("{}\t{}", ip, entry.domain));
+                    result.push('\n');
+                }
             }
             result.push_str(SENTINEL_END);
             result.push('\n');
@@ -94,16 +145,132 @@ impl HostsFile {
             result.push('\n');
         }
 
+        // Files without a final newline keep it that way, unless the last
+        // line written is our own sentinel
+        if !self.trailing_newline && (self.entries.is_empty() || !self.after.is_empty()) {
+            result.pop();
+        }
+
         result
     }
 
-    /// Add an entry. Returns false if the domain already exists.
-    pub fn add_entry(&mut self, domain: &str, ip: &str) -> bool {
+    /// Number of lines the managed block occupies when serialized.
+    fn managed_line_count(&self) -> usize {
+        if self.entries.is_empty() {
+            return 0;
+        }
+        2 + self.entries.iter().map(|e| e.ips.len()).sum::<usize>()
+    }
+
+    /// Structured view of every host line outside the sentinel block.
+    pub fn unmanaged_entries(&self) -> Vec<UnmanagedEntry> {
+        let after_offset = self.before.len() + self.managed_line_count();
+        let numbered = self
+            .before
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l))
+            .chain(
+                self.after
+                    .iter()
+                    .enumerate()
+                    .map(|(i, l)| (after_offset + i + 1, l)),
+            );
+
+        numbered
+            .filter_map(|(line, raw)| {
+                let (disabled, body) = match raw.strip_prefix(DISABLED_PREFIX) {
+                    Some(rest) => (true, rest),
+                    None => (false, raw.as_str()),
+                };
+                let (ip, names) = parse_host_line(body)?;
+                Some(UnmanagedEntry {
+                    line,
+                    ip,
+                    names,
+                    disabled,
+                })
+            })
+            .collect()
+    }
+
+    /// Active unmanaged entries that map `domain` to an address other than
+    /// `ips`, in the same address family (with only `127.0.0.1` written, a
+    /// `::1` line doesn't get in the way).
+    pub fn conflicts(&self, domain: &str, ips: &[&str]) -> Vec<UnmanagedEntry> {
+        let families: Vec<bool> = ips
+            .iter()
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .map(|ip| ip.is_ipv4())
+            .collect();
+        self.unmanaged_entries()
+            .into_iter()
+            .filter(|e| !e.disabled)
+            .filter(|e| e.names.iter().any(|n| n.eq_ignore_ascii_case(domain)))
+            .filter(|e| !ips.contains(&e.ip.as_str()))
+            .filter(|e| match e.ip.parse::<IpAddr>() {
+                Ok(ip) => families.contains(&ip.is_ipv4()),
+                Err(_) => true,
+            })
+            .collect()
+    }
+
+    /// Mutable access to an unmanaged line by its 1-based line number.
+    fn unmanaged_line_mut(&mut self, line: usize) -> Option<&mut String> {
+        let after_offset = self.before.len() + self.managed_line_count();
+        if line >= 1 && line <= self.before.len() {
+            self.before.get_mut(line - 1)
+        } else if line > after_offset {
+            self.after.get_mut(line - after_offset - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Comment out the given unmanaged lines for the session.
+    pub fn disable_lines(&mut self, lines: &[usize]) {
+        for &line in lines {
+            if let Some(raw) = self.unmanaged_line_mut(line) {
+                if !raw.starts_with(DISABLED_PREFIX) {
+                    *raw = format!("{}{}", DISABLED_PREFIX, raw);
+                }
+            }
+        }
+    }
+
+    /// Restore lines that were commented out because they mapped `domain`,
+    /// unless they also name a domain that's still mapped (call after
+    /// removing `domain`'s entry).
+    pub fn enable_conflicts(&mut self, domain: &str) {
+        let lines: Vec<usize> = self
+            .unmanaged_entries()
+            .into_iter()
+            .filter(|e| e.disabled && e.names.iter().any(|n| n.eq_ignore_ascii_case(domain)))
+            .filter(|e| {
+                !e.names.iter().any(|n| {
+                    self.entries
+                        .iter()
+                        .any(|entry| entry.domain.eq_ignore_ascii_case(n))
+                })
+            })
+            .map(|e| e.line)
+            .collect();
+        for line in lines {
+            if let Some(raw) = self.unmanaged_line_mut(line) {
+                if let Some(rest) = raw.strip_prefix(DISABLED_PREFIX) {
+                    *raw = rest.to_string();
+                }
+            }
+        }
+    }
+
+    /// Add an entry for one or more IPs. Returns false if the domain already exists.
+    pub fn add_entry(&mut self, domain: &str, ips: &[&str]) -> bool {
         if self.entries.iter().any(|e| e.domain == domain) {
             return false;
         }
         self.entries.push(HostEntry {
-            ip: ip.to_string(),
+            ips: ips.iter().map(|ip| ip.to_string()).collect(),
             domain: domain.to_string(),
         });
         true
@@ -116,9 +283,14 @@ impl HostsFile {
         self.entries.len() < len_before
     }
 
-    /// Remove all managed entries (for cleanup).
+    /// Remove all managed entries and restore any commented-out lines (for cleanup).
     pub fn remove_all(&mut self) {
         self.entries.clear();
+        for line in self.before.iter_mut().chain(self.after.iter_mut()) {
+            if let Some(rest) = line.strip_prefix(DISABLED_PREFIX) {
+                *line = rest.to_string();
+            }
+        }
     }
 }
 
@@ -157,7 +329,7 @@ mod tests {
     fn test_roundtrip() {
         let original = "127.0.0.1\tlocalhost\n::1\tlocalhost\n";
         let mut hosts = HostsFile::parse(original);
-        hosts.add_entry("test.localhost", "127.0.0.1");
+        hosts.add_entry("test.localhost", &["127.0.0.1"]);
         let serialized = hosts.serialize();
         let reparsed = HostsFile::parse(&serialized);
         assert_eq!(reparsed.entries.len(), 1);
@@ -168,29 +340,140 @@ mod tests {
     #[test]
     fn test_add_duplicate() {
         let mut hosts = HostsFile::parse("");
-        assert!(hosts.add_entry("test.localhost", "127.0.0.1"));
-        assert!(!hosts.add_entry("test.localhost", "127.0.0.1"));
+        assert!(hosts.add_entry("test.localhost", &["127.0.0.1"]));
+        assert!(!hosts.add_entry("test.localhost", &["127.0.0.1"]));
     }
 
     #[test]
     fn test_remove_entry() {
         let mut hosts = HostsFile::parse("");
-        hosts.add_entry("test.localhost", "127.0.0.1");
-        hosts.add_entry("api.localhost", "127.0.0.1");
+        hosts.add_entry("test.localhost", &["127.0.0.1"]);
+        hosts.add_entry("api.localhost", &["127.0.0.1"]);
         assert!(hosts.remove_entry("test.localhost"));
         assert_eq!(hosts.entries.len(), 1);
         assert_eq!(hosts.entries[0].domain, "api.localhost");
     }
 
+    #[test]
+    fn test_dual_stack_entry() {
+        let mut hosts = HostsFile::parse("127.0.0.1\tlocalhost\n");
+        assert!(hosts.add_entry("test.localhost", &["127.0.0.1", "::1"]));
+        let serialized = hosts.serialize();
+        assert!(serialized.contains("127.0.0.1\ttest.localhost\n::1\ttest.localhost\n"));
+
+        let reparsed = HostsFile::parse(&serialized);
+        assert_eq!(reparsed.entries.len(), 1);
+        assert_eq!(reparsed.entries[0].ips, vec!["127.0.0.1", "::1"]);
+
+        let mut reparsed = reparsed;
+        assert!(reparsed.remove_entry("test.localhost"));
+        assert!(!reparsed.serialize().contains("::1\ttest.localhost"));
+    }
+
     #[test]
     fn test_remove_all() {
         let mut hosts = HostsFile::parse("");
-        hosts.add_entry("a.localhost", "127.0.0.1");
-        hosts.add_entry("b.localhost", "127.0.0.1");
+        hosts.add_entry("a.localhost", &["127.0.0.1"]);
+        hosts.add_entry("b.localhost", &["127.0.0.1"]);
         hosts.remove_all();
         assert!(hosts.entries.is_empty());
         // Serializing with no entries should not include sentinel block
         let serialized = hosts.serialize();
         assert!(!serialized.contains("portmap-start"));
     }
+
+    #[test]
+    fn test_roundtrip_is_byte_for_byte() {
+        let original = "# comment\r\n127.0.0.1   localhost  # trailing\r\n\n  \t\n::1 localhost";
+        let hosts = HostsFile::parse(original);
+        assert_eq!(hosts.serialize(), original);
+
+        let with_newline = "127.0.0.1\tlocalhost\n\n";
+        assert_eq!(HostsFile::parse(with_newline).serialize(), with_newline);
+    }
+
+    #[test]
+    fn test_unmanaged_entries() {
+        let content = "\
+# comment
+127.0.0.1\tlocalhost
+# portmap-start (DO NOT EDIT - managed by portmap)
+127.0.0.1\tmy-project.localhost
+# portmap-end
+10.0.0.5 api.localhost api  # staging
+not-an-ip foo
+";
+        let hosts = HostsFile::parse(content);
+        let entries = hosts.unmanaged_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].line, 2);
+        assert_eq!(entries[0].names, vec!["localhost"]);
+        assert_eq!(entries[1].line, 6);
+        assert_eq!(entries[1].ip, "10.0.0.5");
+        assert_eq!(entries[1].names, vec!["api.localhost", "api"]);
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let content = "10.0.0.5 api.localhost\n127.0.0.1 web.localhost\n";
+        let hosts = HostsFile::parse(content);
+        let conflicts = hosts.conflicts("API.localhost", &["127.0.0.1"]);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].line, 1);
+        // Same address isn't a conflict
+        assert!(hosts.conflicts("web.localhost", &["127.0.0.1"]).is_empty());
+    }
+
+    #[test]
+    fn test_conflicts_only_in_written_families() {
+        let content = "::1 api.localhost\n10.0.0.5 api.localhost\nfe80::1 web.localhost\n";
+        let hosts = HostsFile::parse(content);
+        let lines = |domain, ips| -> Vec<usize> {
+            hosts.conflicts(domain, ips).iter().map(|c| c.line).collect()
+        };
+        assert_eq!(lines("api.localhost", &["127.0.0.1"]), vec![2]);
+        assert_eq!(lines("api.localhost", &["::1"]), Vec::<usize>::new());
+        assert_eq!(lines("web.localhost", &["127.0.0.1"]), Vec::<usize>::new());
+        assert_eq!(lines("web.localhost", &["127.0.0.1", "::1"]), vec![3]);
+    }
+
+    #[test]
+    fn test_shared_disabled_line_waits_for_last_mapping() {
+        let original = "10.0.0.5 api.localhost web.localhost\n";
+        let mut hosts = HostsFile::parse(original);
+        hosts.add_entry("api.localhost", &["127.0.0.1"]);
+        hosts.add_entry("web.localhost", &["127.0.0.1"]);
+        hosts.disable_lines(&[1]);
+
+        hosts.remove_entry("api.localhost");
+        hosts.enable_conflicts("api.localhost");
+        // web.localhost is still mapped, so the line stays out of the way
+        assert!(hosts.unmanaged_entries()[0].disabled);
+
+        hosts.remove_entry("web.localhost");
+        hosts.enable_conflicts("web.localhost");
+        assert_eq!(hosts.serialize(), original);
+    }
+
+    #[test]
+    fn test_disable_and_restore_conflicts() {
+        let original = "10.0.0.5 api.localhost\n127.0.0.1 localhost\n";
+        let mut hosts = HostsFile::parse(original);
+        hosts.add_entry("api.localhost", &["127.0.0.1"]);
+        hosts.disable_lines(&[1]);
+        let serialized = hosts.serialize();
+        assert!(serialized.starts_with("#portmap-disabled 10.0.0.5 api.localhost\n"));
+
+        let mut reparsed = HostsFile::parse(&serialized);
+        assert!(reparsed.conflicts("api.localhost", &["127.0.0.1"]).is_empty());
+        assert!(reparsed.unmanaged_entries()[0].disabled);
+
+        reparsed.remove_entry("api.localhost");
+        reparsed.enable_conflicts("api.localhost");
+        assert_eq!(reparsed.serialize(), original);
+
+        // Cleanup restores commented-out lines as well
+        hosts.remove_all();
+        assert_eq!(hosts.serialize(), original);
+    }
 }
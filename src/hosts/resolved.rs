@@ -0,0 +1,168 @@
+use crate::config::ResolvedConfig;
+use crate::hosts::backend::HostsBackend;
+use anyhow::{anyhow, Context, Result};
+use std::process::Command;
+use std::sync::Mutex;
+
+/// Routes mapped domains to a DNS server through systemd-resolved by
+/// setting per-link routing domains (`resolvectl domain <link> ~name`).
+///
+/// The DNS server configured for the link must answer for the mapped
+/// domains, e.g. dnsmasq or portmap's own responder.
+pub struct ResolvedBackend {
+    config: ResolvedConfig,
+    /// DNS server set on the link
+    server: String,
+    /// Routing domains currently set on the link
+    domains: Mutex<Vec<String>>,
+}
+
+impl ResolvedBackend {
+    pub fn new(config: ResolvedConfig, server: String) -> Self {
+        Self {
+            config,
+            server,
+            domains: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn resolvectl(&self, args: &[&str]) -> Result<()> {
+        let output = Command::new(&self.config.resolvectl)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to run {}", self.config.resolvectl.display()))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "resolvectl {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Push the full domain list to the link (resolvectl replaces, not appends).
+    fn apply(&self, domains: &[String]) -> Result<()> {
+        let link = self.config.link.as_str();
+        if domains.is_empty() {
+            return self.resolvectl(&["revert", link]);
+        }
+        self.resolvectl(&["dns", link, &self.server])?;
+        let routing: Vec<String> = domains.iter().map(|d| format!("~{}", d)).collect();
+        let mut args = vec!["domain", link];
+        args.extend(routing.iter().map(|d| d.as_str()));
+        self.resolvectl(&args)
+    }
+}
+
+impl HostsBackend for ResolvedBackend {
+    fn describe(&self) -> String {
+        format!("systemd-resolved link {}", self.config.link)
+    }
+
+    fn add_entry(&self, domain: &str) -> Result<bool> {
+        let mut domains = self.domains.lock().unwrap_or_else(|e| e.into_inner());
+        if domains.iter().any(|d| d == domain) {
+            return Ok(false);
+        }
+        let mut updated = domains.clone();
+        updated.push(domain.to_string());
+        self.apply(&updated)?;
+        *domains = updated;
+        Ok(true)
+    }
+
+    fn remove_entry(&self, domain: &str) -> Result<bool> {
+        let mut domains = self.domains.lock().unwrap_or_else(|e| e.into_inner());
+        if !domains.iter().any(|d| d == domain) {
+            return Ok(false);
+        }
+        let updated: Vec<String> = domains.iter().filter(|d| *d != domain).cloned().collect();
+        self.apply(&updated)?;
+        *domains = updated;
+        Ok(true)
+    }
+
+    fn restore_all(&self) -> Result<()> {
+        // Called from the panic hook, so tolerate a poisoned lock
+        let mut domains = self.domains.lock().unwrap_or_else(|e| e.into_inner());
+        self.resolvectl(&["revert", &self.config.link])?;
+        domains.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    /// Install a fake `resolvectl` that logs its arguments, one call per line.
+    fn fake_resolvectl(dir: &TempDir) -> (ResolvedBackend, std::path::PathBuf) {
+        let log = dir.path().join("calls.log");
+        let script = dir.path().join("resolvectl");
+        std::fs::write(
+            &script,
+            format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = ResolvedConfig {
+            resolvectl: script,
+            link: "portmap0".to_string(),
+            dns: None,
+        };
+        (ResolvedBackend::new(config, "127.0.0.1:5353".to_string()), log)
+    }
+
+    #[test]
+    fn test_domains_set_on_link() {
+        let dir = TempDir::new().unwrap();
+        let (backend, log) = fake_resolvectl(&dir);
+
+        assert!(backend.add_entry("api.localhost").unwrap());
+        assert!(!backend.add_entry("api.localhost").unwrap());
+        assert!(backend.add_entry("web.localhost").unwrap());
+        assert!(backend.remove_entry("api.localhost").unwrap());
+        assert!(!backend.remove_entry("api.localhost").unwrap());
+        backend.restore_all().unwrap();
+
+        let calls = std::fs::read_to_string(&log).unwrap();
+        let calls: Vec<&str> = calls.lines().collect();
+        assert_eq!(
+            calls,
+            vec![
+                "dns portmap0 127.0.0.1:5353",
+                "domain portmap0 ~api.localhost",
+                "dns portmap0 127.0.0.1:5353",
+                "domain portmap0 ~api.localhost ~web.localhost",
+                "dns portmap0 127.0.0.1:5353",
+                "domain portmap0 ~web.localhost",
+                "revert portmap0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_removing_last_domain_reverts_link() {
+        let dir = TempDir::new().unwrap();
+        let (backend, log) = fake_resolvectl(&dir);
+        backend.add_entry("api.localhost").unwrap();
+        backend.remove_entry("api.localhost").unwrap();
+        let calls = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(calls.lines().last(), Some("revert portmap0"));
+    }
+
+    #[test]
+    fn test_failing_resolvectl_leaves_state_unchanged() {
+        let config = ResolvedConfig {
+            resolvectl: "false".into(),
+            ..ResolvedConfig::default()
+        };
+        let backend = ResolvedBackend::new(config, "127.0.0.1:1053".to_string());
+        assert!(backend.add_entry("api.localhost").is_err());
+        assert!(!backend.remove_entry("api.localhost").unwrap());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::app::Mapping;
+use crate::hosts::backend::HostsBackend;
+use crate::hosts::parser::HostsFile;
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+/// Editors and tools often write, rename and chmod in quick succession;
+/// wait for the burst to settle before re-reading.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// External edits that removed or changed our entries, for the status bar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostsDrift {
+    /// How many times the block had to be repaired this session
+    pub count: usize,
+    /// What happened the last time
+    pub message: String,
+}
+
+/// Live domains whose managed entry is missing from `content`, or no
+/// longer written with exactly `ips`.
+pub fn find_drift(content: &str, ips: &[&str], domains: &[String]) -> Vec<String> {
+    let hosts = HostsFile::parse(content);
+    domains
+        .iter()
+        .filter(|domain| {
+            match hosts.entries.iter().find(|e| &e.domain == *domain) {
+                Some(entry) => entry.ips != ips,
+                None => true,
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+/// Watch the hosts file and re-apply entries for live mappings whenever
+/// another process rewrites it without them.
+pub fn spawn_hosts_watcher(
+    path: PathBuf,
+    ips: &'static [&'static str],
+    hosts: Arc<dyn HostsBackend>,
+    mappings_rx: watch::Receiver<Vec<Mapping>>,
+    drift_tx: watch::Sender<Option<HostsDrift>>,
+) -> Result<()> {
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let file_name = path.file_name().map(|n| n.to_os_string());
+
+    // Watch the directory: tools that replace the file via rename would
+    // otherwise leave us watching a dead inode
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            if event
+                .paths
+                .iter()
+                .any(|p| p.file_name().map(|n| n.to_os_string()) == file_name)
+            {
+                let _ = event_tx.send(());
+            }
+        }
+    })?;
+    let dir = path
+        .parent()
+        .context("hosts file path has no parent directory")?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    tokio::spawn(async move {
+        // Keep the watcher alive for as long as the task runs
+        let _watcher = watcher;
+        while event_rx.recv().await.is_some() {
+            tokio::time::sleep(DEBOUNCE).await;
+            while event_rx.try_recv().is_ok() {}
+
+            let domains: Vec<String> = mappings_rx
+                .borrow()
+                .iter()
+                .filter(|m| m.enabled)
+                .map(|m| m.domain.clone())
+                .collect();
+            let message = match repair(&path, ips, hosts.as_ref(), &domains) {
+                Ok(repaired) if repaired.is_empty() => continue,
+                Ok(repaired) => format!("re-applied {}", repaired.join(", ")),
+                Err(e) => format!("failed to re-apply entries: {}", e),
+            };
+            drift_tx.send_modify(|drift| {
+                let count = drift.as_ref().map_or(0, |d| d.count) + 1;
+                *drift = Some(HostsDrift { count, message });
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// Re-apply the drifted entries through the backend. Returns the repaired domains.
+fn repair(
+    path: &std::path::Path,
+    ips: &[&str],
+    hosts: &dyn HostsBackend,
+    domains: &[String],
+) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let drifted = find_drift(&content, ips, domains);
+    for domain in &drifted {
+        // Changed entries are replaced; missing ones are simply added
+        hosts.remove_entry(domain)?;
+        hosts.add_entry(domain)?;
+    }
+    Ok(drifted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hosts::manager::HostsManager;
+    use tempfile::TempDir;
+
+    const DUAL: &[&str] = &["127.0.0.1", "::1"];
+
+    fn domains(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_no_drift_when_block_matches() {
+        let mut hosts = HostsFile::parse("127.0.0.1\tlocalhost\n");
+        hosts.add_entry("api.localhost", DUAL);
+        let drift = find_drift(&hosts.serialize(), DUAL, &domains(&["api.localhost"]));
+        assert!(drift.is_empty());
+    }
+
+    #[test]
+    fn test_drift_when_block_removed_or_changed() {
+        let drift = find_drift(
+            "127.0.0.1\tlocalhost\n",
+            DUAL,
+            &domains(&["api.localhost"]),
+        );
+        assert_eq!(drift, vec!["api.localhost"]);
+
+        let mut hosts = HostsFile::parse("");
+        hosts.add_entry("api.localhost", &["127.0.0.1"]);
+        hosts.add_entry("web.localhost", DUAL);
+        let drift = find_drift(
+            &hosts.serialize(),
+            DUAL,
+            &domains(&["api.localhost", "web.localhost"]),
+        );
+        assert_eq!(drift, vec!["api.localhost"]);
+    }
+
+    #[tokio::test]
+    async fn test_watcher_reapplies_entries() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("hosts");
+        std::fs::write(&path, "127.0.0.1\tlocalhost\n").unwrap();
+
+        let manager = Arc::new(HostsManager::with_path(path.clone()));
+        manager.add_entry("api.localhost").unwrap();
+
+        let mapping = Mapping::new("api.localhost".to_string(), 3000);
+        let (_mappings_tx, mappings_rx) = watch::channel(vec![mapping]);
+        let (drift_tx, mut drift_rx) = watch::channel(None);
+        spawn_hosts_watcher(path.clone(), DUAL, manager, mappings_rx, drift_tx).unwrap();
+
+        // Another tool rewrites the file without our block
+        std::fs::write(&path, "127.0.0.1\tlocalhost\n10.0.0.1\tvpn.internal\n").unwrap();
+
+        tokio::time::timeout(Duration::from_secs(10), drift_rx.changed())
+            .await
+            .expect("drift was not detected")
+            .unwrap();
+        let drift = drift_rx.borrow().clone().unwrap();
+        assert_eq!(drift.count, 1);
+        assert!(drift.message.contains("api.localhost"));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("10.0.0.1\tvpn.internal"));
+        assert!(content.contains("127.0.0.1\tapi.localhost"));
+        assert!(content.contains("::1\tapi.localhost"));
+    }
+}
@@ -1,22 +1,44 @@
 mod app;
 mod cleanup;
+mod cli;
+mod config;
+mod dns;
 mod error;
+mod events;
 mod hosts;
+mod privilege;
+mod probe;
 mod proxy;
 mod tui;
 mod update;
 
-use crate::app::Mapping;
+use crate::app::{Mapping, SessionInfo};
 use crate::cleanup::{install_panic_hook, run_cleanup, spawn_signal_handler};
-use crate::hosts::manager::HostsManager;
-use crate::proxy::server::run_proxy;
-use crate::tui::terminal::run_tui;
+use crate::cli::{Cli, UpdateAction};
+use crate::config::{Config, ProxyConfig, ResolverBackend};
+use crate::dns::server::DnsServer;
+use crate::events::{runnable_hook, spawn_event_handler};
+use crate::hosts::backend::{self, HostsBackend};
+use crate::hosts::helper::{run_helper, HelperClient, HelperSetup, HELPER_FLAG};
+use crate::hosts::manager::HOSTS_PATH;
+use crate::hosts::watcher::spawn_hosts_watcher;
+use crate::privilege::{drop_privileges, is_root, InvokingUser, RunMode};
+use crate::probe::spawn_prober;
+use crate::proxy::handler::load_disabled_page;
+use crate::proxy::metrics::ProxyMetrics;
+use crate::proxy::server::{bind_listeners, listen_addrs, run_proxy, ProxyPhase, ProxyStatus};
+use crate::tui::keymap::Keymap;
+use crate::tui::terminal::{run_tui, TuiChannels};
+use crate::tui::theme::Theme;
 use anyhow::Result;
 use std::path::PathBuf;
-use tokio::sync::watch;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 
-fn escalate_if_needed() -> Result<()> {
-    if unsafe { libc::geteuid() == 0 } {
+/// Re-run under sudo, but only when the selected mode actually needs root.
+fn escalate_if_needed(mode: RunMode) -> Result<()> {
+    if mode != RunMode::Root || is_root() {
         return Ok(());
     }
     let exe = std::env::current_exe()?;
@@ -28,62 +50,217 @@ fn escalate_if_needed() -> Result<()> {
     std::process::exit(status.code().unwrap_or(1));
 }
 
+/// Pick the hosts backend, and drop root if configured to.
+///
+/// When dropping, the real backend runs in a root helper process and this
+/// process talks to it over a pipe; everything else (proxy, TUI, upstream
+/// connections) then runs as the invoking user.
+fn setup_hosts_backend(config: &Config) -> Result<Arc<dyn HostsBackend>> {
+    let user = match InvokingUser::from_env() {
+        Some(user) if config.privileges.drop && is_root() => user,
+        // Staying root: commands and paths come from the system config,
+        // exactly as for the helper
+        _ if is_root() => return HelperSetup::from_config(config).backend(),
+        _ => return backend::from_config(config),
+    };
+    // With no system resolver to edit there's nothing to keep root for
+    if config.hosts.backend == ResolverBackend::None {
+        drop_privileges(user)?;
+        return backend::from_config(config);
+    }
+
+    let setup = HelperSetup::from_config(config);
+    let describe = setup.backend()?.describe();
+    let hosts_path = (config.hosts.backend == ResolverBackend::HostsFile)
+        .then(|| PathBuf::from(HOSTS_PATH));
+    let client = HelperClient::spawn(setup, describe, hosts_path)?;
+    drop_privileges(user)?;
+    Ok(Arc::new(client))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args: Vec<String> = std::env::args().collect();
 
-    // Handle --cleanup flag
-    if args.iter().any(|a| a == "--cleanup") {
-        escalate_if_needed()?;
-        return run_cleanup();
+    // Privileged hosts helper, spawned by the main process below
+    if args.iter().any(|a| a == HELPER_FLAG) {
+        return run_helper();
+    }
+
+    let cli = Cli::parse(args.into_iter().skip(1))?;
+    // Also the updater's self-test, so it must not depend on anything else
+    if cli.version {
+        println!("portmap {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
     }
 
-    escalate_if_needed()?;
+    let mut config = Config::load()?;
+
+    if let Some(action) = cli.update {
+        return update::run_update_command(&config.update, action == UpdateAction::Rollback);
+    }
 
-    if let Err(e) = update::check_for_update() {
-        let _ = e;
+    // Handle --cleanup flag (only system resolvers need root to clean up)
+    if cli.cleanup {
+        if config.hosts.backend != ResolverBackend::None {
+            escalate_if_needed(RunMode::Root)?;
+        }
+        return run_cleanup(&config);
     }
 
-    let hosts_path = PathBuf::from("/etc/hosts");
+    let rootless = cli.rootless || config.proxy.rootless;
+    let default_port = if rootless { ProxyConfig::ROOTLESS_PORT } else { 80 };
+    let port = cli.port.or(config.proxy.port).unwrap_or(default_port);
+    // Command-line listeners replace the configured ones
+    let specs = if cli.listen.is_empty() {
+        &config.proxy.listen
+    } else {
+        &cli.listen
+    };
+    let addrs = listen_addrs(specs, port)?;
+    let disabled_page = load_disabled_page(config.proxy.disabled_page.as_deref())?;
+    let keymap = Keymap::from_config(&config.tui.keys)?;
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let theme = Theme::from_config(&config.tui, no_color)?;
+    let lowest_port = addrs.iter().map(|a| a.port()).min().unwrap_or(port);
+    let mode = RunMode::select(&config, rootless, lowest_port);
+
+    escalate_if_needed(mode)?;
+    if !mode.edits_system_resolver() {
+        // Browsers resolve *.localhost to loopback by themselves
+        config.hosts.backend = ResolverBackend::None;
+    }
+
+    // Bind everything that needs root before dropping it
+    let listeners = bind_listeners(&addrs)?;
+    let bound = listeners
+        .iter()
+        .filter_map(|l| l.local_addr().ok())
+        .collect();
+    let dns_server = if config.dns.enabled {
+        Some(DnsServer::bind(config.dns.clone()).await?)
+    } else {
+        None
+    };
+
+    let hosts = setup_hosts_backend(&config)?;
+
+    // In the background, and only once no longer root
+    let (update_tx, update_rx) = watch::channel(None);
+    update::spawn_update_check(config.update.clone(), update_tx);
 
     // Install panic hook for crash cleanup
-    install_panic_hook(hosts_path.clone());
+    install_panic_hook(hosts.clone());
 
     // Shutdown signal channel
     let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
     // Spawn signal handlers (Ctrl+C, SIGTERM)
-    spawn_signal_handler(hosts_path.clone(), shutdown_tx.clone());
+    spawn_signal_handler(hosts.clone(), shutdown_tx.clone());
 
     // Shared mappings channel (TUI writes, proxy reads)
     let (mappings_tx, mappings_rx) = watch::channel::<Vec<Mapping>>(Vec::new());
 
-    let hosts_manager = HostsManager::new();
+    // Status probes run in the background and report back to the TUI
+    let (probe_tx, probe_rx) = mpsc::channel(64);
+    spawn_prober(mappings_rx.clone(), config.health.clone(), probe_tx);
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    let mut notifications = config.notifications.clone();
+    notifications.hook = runnable_hook(&notifications, is_root());
+    spawn_event_handler(notifications, event_rx);
+
+    // Re-apply our entries if another tool rewrites /etc/hosts
+    let (drift_tx, drift_rx) = watch::channel(None);
+    if config.hosts.backend == ResolverBackend::HostsFile {
+        if let Err(e) = spawn_hosts_watcher(
+            PathBuf::from(HOSTS_PATH),
+            config.hosts.ip_family.loopback_ips(),
+            hosts.clone(),
+            mappings_rx.clone(),
+            drift_tx,
+        ) {
+            eprintln!("Warning: not watching {} for changes: {}", HOSTS_PATH, e);
+        }
+    }
 
     // Run proxy and TUI concurrently
     let proxy_shutdown_rx = shutdown_rx.clone();
     let proxy_mappings_rx = mappings_rx.clone();
 
+    let drain_timeout = Duration::from_secs(config.proxy.drain_timeout_secs);
+    let (proxy_status_tx, proxy_status_rx) = watch::channel(ProxyStatus::default());
+    let metrics = Arc::new(ProxyMetrics::default());
+    let proxy_metrics = metrics.clone();
+
     let proxy_handle = tokio::spawn(async move {
-        if let Err(e) = run_proxy(proxy_mappings_rx, proxy_shutdown_rx).await {
+        let result = run_proxy(
+            listeners,
+            proxy_mappings_rx,
+            proxy_shutdown_rx,
+            drain_timeout,
+            proxy_status_tx.clone(),
+            disabled_page,
+            proxy_metrics,
+        )
+        .await;
+        if let Err(e) = result {
             eprintln!("Proxy error: {}", e);
         }
+        // Let the TUI stop waiting even if the proxy failed
+        proxy_status_tx.send_modify(|s| s.phase = ProxyPhase::Stopped);
     });
 
+    let dns_handle = if let Some(server) = dns_server {
+        let dns_mappings_rx = mappings_rx.clone();
+        let dns_shutdown_rx = shutdown_rx.clone();
+        Some(tokio::spawn(async move {
+            if let Err(e) = server.run(dns_mappings_rx, dns_shutdown_rx).await {
+                eprintln!("DNS error: {}", e);
+            }
+        }))
+    } else {
+        None
+    };
+
     // Run TUI on the main task (it needs terminal access)
-    let tui_result = run_tui(mappings_tx, hosts_manager, shutdown_rx).await;
+    let tui_result = run_tui(
+        TuiChannels {
+            mappings: mappings_tx,
+            probes: probe_rx,
+            events: event_tx,
+            drift: drift_rx,
+            update: update_rx,
+            proxy: proxy_status_rx,
+            shutdown: shutdown_tx.clone(),
+            metrics,
+        },
+        hosts.clone(),
+        config.clone(),
+        keymap,
+        theme,
+        SessionInfo {
+            mode,
+            listen: bound,
+            self_update: update::self_update_problem().is_none(),
+        },
+    )
+    .await;
 
-    // TUI exited — signal shutdown to proxy
+    // Normally already sent by the TUI; a forced quit skips the drain
     let _ = shutdown_tx.send(true);
+    proxy_handle.abort();
+    let _ = proxy_handle.await;
 
-    // Clean up /etc/hosts
-    let manager = HostsManager::new();
-    if let Err(e) = manager.restore_all() {
-        eprintln!("Warning: failed to clean up /etc/hosts: {}", e);
+    // Clean up /etc/hosts (or whichever backend is configured) once no
+    // request can be routed through it any more
+    if let Err(e) = hosts.restore_all() {
+        eprintln!("Warning: failed to clean up {}: {}", hosts.describe(), e);
     }
 
-    // Wait for proxy to finish
-    let _ = proxy_handle.await;
+    // Wait for the DNS responder to finish
+    if let Some(handle) = dns_handle {
+        let _ = handle.await;
+    }
 
     tui_result
 }
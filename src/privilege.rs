@@ -0,0 +1,123 @@
+use crate::config::{Config, ResolverBackend};
+use anyhow::{bail, Context, Result};
+
+/// Linux capability number of `CAP_NET_BIND_SERVICE`.
+const CAP_NET_BIND_SERVICE: u32 = 10;
+
+/// How portmap got (or avoided needing) the rights it runs with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunMode {
+    /// Started as root via sudo; privileges may be dropped after setup
+    Root,
+    /// Unprivileged, binding low ports via `setcap cap_net_bind_service`
+    Capability,
+    /// Unprivileged on a high port
+    Rootless,
+}
+
+impl RunMode {
+    /// Decide the mode before anything is bound.
+    ///
+    /// Only `Root` escalates with sudo. The other modes leave system name
+    /// resolution alone: browsers resolve `*.localhost` to loopback on
+    /// their own.
+    pub fn select(config: &Config, rootless_requested: bool, port: u16) -> Self {
+        if rootless_requested {
+            return RunMode::Rootless;
+        }
+        if is_root() {
+            return RunMode::Root;
+        }
+        if has_net_bind_capability() {
+            return RunMode::Capability;
+        }
+        // Nothing configured actually needs root
+        let dns_unprivileged = !config.dns.enabled || config.dns.listen.port() >= 1024;
+        if port >= 1024 && config.hosts.backend == ResolverBackend::None && dns_unprivileged {
+            return RunMode::Rootless;
+        }
+        RunMode::Root
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RunMode::Root => "root",
+            RunMode::Capability => "cap_net_bind_service",
+            RunMode::Rootless => "rootless",
+        }
+    }
+
+    /// Whether this mode may edit /etc/hosts (or another system resolver).
+    pub fn edits_system_resolver(self) -> bool {
+        self == RunMode::Root
+    }
+}
+
+/// Whether the effective capability set includes `CAP_NET_BIND_SERVICE`.
+#[cfg(target_os = "linux")]
+pub fn has_net_bind_capability() -> bool {
+    let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+        return false;
+    };
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_NET_BIND_SERVICE) != 0)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn has_net_bind_capability() -> bool {
+    false
+}
+
+/// The user who invoked portmap through sudo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvokingUser {
+    pub name: String,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+impl InvokingUser {
+    /// Read `SUDO_USER`/`SUDO_UID`/`SUDO_GID`. `None` when not running
+    /// under sudo (or when sudo was run by root itself, where there's
+    /// nothing to drop to).
+    pub fn from_env() -> Option<Self> {
+        let name = std::env::var("SUDO_USER").ok()?;
+        let uid = std::env::var("SUDO_UID").ok()?.parse().ok()?;
+        let gid = std::env::var("SUDO_GID").ok()?.parse().ok()?;
+        if uid == 0 {
+            return None;
+        }
+        Some(Self { name, uid, gid })
+    }
+}
+
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Permanently switch the whole process to `user`.
+///
+/// Supplementary groups go first (the user's own, as at login), then the
+/// gid, then the uid — after `setuid` we could no longer change the others.
+pub fn drop_privileges(user: InvokingUser) -> Result<()> {
+    let name = std::ffi::CString::new(user.name).context("invalid user name")?;
+    unsafe {
+        if libc::initgroups(name.as_ptr(), user.gid as _) != 0 {
+            return Err(std::io::Error::last_os_error()).context("initgroups failed");
+        }
+        if libc::setgid(user.gid) != 0 {
+            return Err(std::io::Error::last_os_error()).context("setgid failed");
+        }
+        if libc::setuid(user.uid) != 0 {
+            return Err(std::io::Error::last_os_error()).context("setuid failed");
+        }
+        // Make sure there's no way back
+        if libc::setuid(0) == 0 {
+            bail!("privileges were not dropped: setuid(0) still succeeds");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,514 @@
+//! Mapping status probes.
+//!
+//! A plain TCP connect tells whether anything listens on the port. Mappings
+//! with a health check also get an HTTP request, so a server that accepts
+//! connections but answers 500s (or is still compiling) isn't shown as up.
+//!
+//! Probing runs in a background task, all mappings at once, so a port that
+//! swallows SYNs can't hold up the UI or the other mappings.
+
+use crate::app::{Mapping, MappingStatus};
+use crate::config::HealthConfig;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Deserialize;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch};
+use tokio::time::timeout;
+
+/// An inclusive range of acceptable HTTP status codes, written `200-399`
+/// (or a single code, `204`).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct StatusRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl StatusRange {
+    pub fn contains(self, code: u16) -> bool {
+        (self.min..=self.max).contains(&code)
+    }
+}
+
+impl Default for StatusRange {
+    fn default() -> Self {
+        Self { min: 200, max: 399 }
+    }
+}
+
+impl FromStr for StatusRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s.split_once('-').unwrap_or((s, s));
+        let parse = |code: &str| match code.trim().parse::<u16>() {
+            Ok(code) if (100..=599).contains(&code) => Ok(code),
+            _ => Err(format!("Invalid status code: {}", code.trim())),
+        };
+        let (min, max) = (parse(min)?, parse(max)?);
+        if min > max {
+            return Err(format!("Invalid status range: {}", s));
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl TryFrom<String> for StatusRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for StatusRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+/// An HTTP health check for one mapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthCheck {
+    /// Request path, e.g. `/healthz`
+    pub path: String,
+    pub expected: StatusRange,
+    pub timeout: Duration,
+}
+
+impl HealthCheck {
+    /// Parse `PATH [RANGE] [TIMEOUT]`, e.g. `/healthz 200-299 500ms`.
+    /// Omitted parts come from `defaults`; an empty spec means no check.
+    pub fn parse(spec: &str, defaults: &HealthConfig) -> Result<Option<Self>, String> {
+        let mut parts = spec.split_whitespace();
+        let Some(path) = parts.next() else {
+            return Ok(None);
+        };
+        if !path.starts_with('/') || !path.chars().all(|c| c.is_ascii_graphic()) {
+            return Err("Health path must start with /".to_string());
+        }
+
+        let mut check = HealthCheck {
+            path: path.to_string(),
+            expected: defaults.expected,
+            timeout: Duration::from_millis(defaults.timeout_ms),
+        };
+        for part in parts {
+            if let Some(timeout) = parse_duration(part) {
+                check.timeout = timeout;
+            } else {
+                check.expected = part.parse()?;
+            }
+        }
+        Ok(Some(check))
+    }
+}
+
+impl fmt::Display for HealthCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}ms",
+            self.path,
+            self.expected,
+            self.timeout.as_millis()
+        )
+    }
+}
+
+/// `500ms` or `2s`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    if let Some(ms) = s.strip_suffix("ms") {
+        return ms.parse().ok().map(Duration::from_millis);
+    }
+    s.strip_suffix('s')?.parse().ok().map(Duration::from_secs)
+}
+
+/// What one probe found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub status: MappingStatus,
+    /// Time to connect, or to the response status line for health checks
+    pub latency: Option<Duration>,
+}
+
+enum Failure {
+    Unreachable,
+    TimedOut,
+}
+
+/// Probe `mapping`, using `default_timeout` unless it has a health check
+/// with its own.
+pub async fn probe(mapping: &Mapping, default_timeout: Duration) -> Probe {
+    let limit = mapping
+        .health
+        .as_ref()
+        .map_or(default_timeout, |h| h.timeout);
+    let started = Instant::now();
+    let result = match &mapping.health {
+        None => connect(mapping.port, limit).await.map(|_| None),
+        Some(health) => timeout(limit, request(mapping, health))
+            .await
+            .unwrap_or(Err(Failure::TimedOut))
+            .map(Some),
+    };
+    let latency = started.elapsed();
+
+    // Until a health check has passed once, a server that isn't up yet
+    // (or whose dev proxy answers 502-504) is still starting
+    let never_up = matches!(
+        mapping.status,
+        MappingStatus::Unknown | MappingStatus::Starting
+    );
+    let status = match (result, &mapping.health) {
+        (Ok(None), _) => MappingStatus::Active,
+        (Ok(Some(code)), Some(health)) if health.expected.contains(code) => {
+            MappingStatus::Healthy
+        }
+        (Ok(Some(code)), _) if never_up && (502..=504).contains(&code) => {
+            MappingStatus::Starting
+        }
+        (Ok(Some(code)), _) => MappingStatus::Unhealthy(code),
+        (Err(Failure::Unreachable), Some(_)) if never_up => MappingStatus::Starting,
+        (Err(Failure::Unreachable), _) => MappingStatus::PortUnreachable,
+        (Err(Failure::TimedOut), _) => MappingStatus::Timeout,
+    };
+    let latency = matches!(
+        status,
+        MappingStatus::Active | MappingStatus::Healthy | MappingStatus::Unhealthy(_)
+    )
+    .then_some(latency);
+    Probe { status, latency }
+}
+
+/// Whether anything accepts connections on `port`.
+pub async fn is_listening(port: u16, limit: Duration) -> bool {
+    connect(port, limit).await.is_ok()
+}
+
+/// Connect over IPv4 and IPv6 loopback at once, all within `limit`.
+/// IPv4 wins when both are ready, like the proxy prefers it.
+async fn connect(port: u16, limit: Duration) -> Result<TcpStream, Failure> {
+    let v4 = TcpStream::connect(SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+    let v6 = TcpStream::connect(SocketAddr::from((Ipv6Addr::LOCALHOST, port)));
+    let race = async {
+        tokio::pin!(v4, v6);
+        let (mut v4_failed, mut v6_failed) = (false, false);
+        loop {
+            tokio::select! {
+                biased;
+                result = &mut v4, if !v4_failed => match result {
+                    Ok(stream) => return Some(stream),
+                    Err(_) => v4_failed = true,
+                },
+                result = &mut v6, if !v6_failed => match result {
+                    Ok(stream) => return Some(stream),
+                    Err(_) => v6_failed = true,
+                },
+                else => return None,
+            }
+        }
+    };
+    match timeout(limit, race).await {
+        Ok(Some(stream)) => Ok(stream),
+        Ok(None) => Err(Failure::Unreachable),
+        Err(_) => Err(Failure::TimedOut),
+    }
+}
+
+/// Send the health request and return the response status code.
+async fn request(mapping: &Mapping, health: &HealthCheck) -> Result<u16, Failure> {
+    let mut stream = connect(mapping.port, health.timeout).await?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: portmap\r\nConnection: close\r\n\r\n",
+        health.path, mapping.domain
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|_| Failure::Unreachable)?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut status_line)
+        .await
+        .map_err(|_| Failure::Unreachable)?;
+    // "HTTP/1.1 200 OK"
+    status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or(Failure::Unreachable)
+}
+
+/// A probe result for the mapping it was taken from.
+#[derive(Debug, Clone)]
+pub struct ProbeReport {
+    pub domain: String,
+    pub port: u16,
+    pub probe: Probe,
+}
+
+/// What decides whether a mapping needs probing again right away.
+fn targets(mappings: &[Mapping]) -> Vec<(String, u16, Option<HealthCheck>)> {
+    mappings
+        .iter()
+        .filter(|m| m.enabled)
+        .map(|m| (m.domain.clone(), m.port, m.health.clone()))
+        .collect()
+}
+
+/// Probe every enabled mapping in rounds, reporting each result as soon as it's in.
+///
+/// Rounds start `interval_ms` apart; while no status changes the gap
+/// doubles up to `max_interval_ms`. Adding or changing a mapping starts a
+/// round immediately. Stops once `reports` is closed.
+pub fn spawn_prober(
+    mut mappings_rx: watch::Receiver<Vec<Mapping>>,
+    config: HealthConfig,
+    reports: mpsc::Sender<ProbeReport>,
+) {
+    let min = Duration::from_millis(config.interval_ms.max(1));
+    let max = Duration::from_millis(config.max_interval_ms).max(min);
+    let probe_timeout = Duration::from_millis(config.timeout_ms);
+
+    tokio::spawn(async move {
+        let mut interval = min;
+        loop {
+            let mappings = mappings_rx.borrow_and_update().clone();
+            let probed = targets(&mappings);
+
+            let mut changed = false;
+            let mut probes: FuturesUnordered<_> = mappings
+                .iter()
+                .filter(|m| m.enabled)
+                .map(|m| async move { (m, probe(m, probe_timeout).await) })
+                .collect();
+            while let Some((mapping, result)) = probes.next().await {
+                changed |= result.status != mapping.status;
+                let report = ProbeReport {
+                    domain: mapping.domain.clone(),
+                    port: mapping.port,
+                    probe: result,
+                };
+                if reports.send(report).await.is_err() {
+                    return;
+                }
+            }
+            interval = if changed { min } else { (interval * 2).min(max) };
+
+            // Wait out the interval, unless the mappings change meanwhile
+            // (status updates alone don't count)
+            let sleep = tokio::time::sleep(interval);
+            tokio::pin!(sleep);
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    result = mappings_rx.changed() => {
+                        if result.is_err() {
+                            return;
+                        }
+                        if targets(&mappings_rx.borrow_and_update()) != probed {
+                            interval = min;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn defaults() -> HealthConfig {
+        HealthConfig::default()
+    }
+
+    /// A server answering every request with `status` after `delay`.
+    async fn server(status: u16, delay: Duration) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    tokio::time::sleep(delay).await;
+                    let response = format!("HTTP/1.1 {} X\r\ncontent-length: 0\r\n\r\n", status);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    fn mapping(port: u16, health: Option<&str>) -> Mapping {
+        let mut mapping = Mapping::new("app.localhost".to_string(), port);
+        mapping.health = health.and_then(|h| HealthCheck::parse(h, &defaults()).unwrap());
+        mapping
+    }
+
+    /// A port nothing listens on.
+    async fn closed_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn test_status_range() {
+        assert_eq!("200-299".parse(), Ok(StatusRange { min: 200, max: 299 }));
+        assert_eq!("204".parse(), Ok(StatusRange { min: 204, max: 204 }));
+        assert!("299-200".parse::<StatusRange>().is_err());
+        assert!("2xx".parse::<StatusRange>().is_err());
+        assert!("99".parse::<StatusRange>().is_err());
+        assert!(StatusRange::default().contains(302));
+        assert!(!StatusRange::default().contains(404));
+    }
+
+    #[test]
+    fn test_parse_health_spec() {
+        assert_eq!(HealthCheck::parse("  ", &defaults()), Ok(None));
+
+        let check = HealthCheck::parse("/healthz", &defaults()).unwrap().unwrap();
+        assert_eq!(check.path, "/healthz");
+        assert_eq!(check.expected, StatusRange::default());
+        assert_eq!(check.timeout, Duration::from_millis(defaults().timeout_ms));
+
+        let check = HealthCheck::parse("/up 200-299 500ms", &defaults())
+            .unwrap()
+            .unwrap();
+        assert_eq!(check.expected, StatusRange { min: 200, max: 299 });
+        assert_eq!(check.timeout, Duration::from_millis(500));
+        assert_eq!(check.to_string(), "/up 200-299 500ms");
+
+        assert!(HealthCheck::parse("healthz", &defaults()).is_err());
+        assert!(HealthCheck::parse("/up 2xx", &defaults()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe() {
+        let port = server(200, Duration::ZERO).await;
+        let result = probe(&mapping(port, None), Duration::from_secs(1)).await;
+        assert_eq!(result.status, MappingStatus::Active);
+        assert!(result.latency.is_some());
+        assert!(is_listening(port, Duration::from_secs(1)).await);
+
+        let port = closed_port().await;
+        let result = probe(&mapping(port, None), Duration::from_secs(1)).await;
+        assert_eq!(result.status, MappingStatus::PortUnreachable);
+        assert_eq!(result.latency, None);
+        assert!(!is_listening(port, Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_connect_finds_ipv6_only_listener() {
+        // Not every sandbox has IPv6 loopback
+        let Ok(listener) = tokio::net::TcpListener::bind("[::1]:0").await else {
+            return;
+        };
+        let port = listener.local_addr().unwrap().port();
+        let Ok(stream) = connect(port, Duration::from_secs(1)).await else {
+            panic!("no connection over IPv6");
+        };
+        assert!(stream.peer_addr().unwrap().is_ipv6());
+    }
+
+    #[tokio::test]
+    async fn test_health_probe() {
+        let timeout = Duration::from_secs(1);
+        let port = server(200, Duration::ZERO).await;
+        let result = probe(&mapping(port, Some("/healthz")), timeout).await;
+        assert_eq!(result.status, MappingStatus::Healthy);
+        assert!(result.latency.is_some());
+
+        let port = server(500, Duration::ZERO).await;
+        let result = probe(&mapping(port, Some("/healthz")), timeout).await;
+        assert_eq!(result.status, MappingStatus::Unhealthy(500));
+
+        let port = server(200, Duration::from_secs(5)).await;
+        let result = probe(&mapping(port, Some("/healthz 100ms")), timeout).await;
+        assert_eq!(result.status, MappingStatus::Timeout);
+    }
+
+    #[tokio::test]
+    async fn test_prober_reports_concurrently() {
+        let slow = server(200, Duration::from_secs(5)).await;
+        let fast = server(200, Duration::ZERO).await;
+        let mut slow = mapping(slow, Some("/healthz 3s"));
+        slow.domain = "slow.localhost".to_string();
+        let fast = mapping(fast, None);
+
+        let (mappings_tx, mappings_rx) = watch::channel(vec![slow, fast]);
+        let (reports_tx, mut reports_rx) = mpsc::channel(16);
+        spawn_prober(mappings_rx, HealthConfig::default(), reports_tx);
+
+        // The fast mapping must not wait for the slow one
+        let report = tokio::time::timeout(Duration::from_secs(1), reports_rx.recv())
+            .await
+            .expect("fast probe was held up")
+            .unwrap();
+        assert_eq!(report.domain, "app.localhost");
+        assert_eq!(report.probe.status, MappingStatus::Active);
+        drop(mappings_tx);
+    }
+
+    #[tokio::test]
+    async fn test_prober_probes_new_mappings_immediately() {
+        let config = HealthConfig {
+            interval_ms: 60_000,
+            ..HealthConfig::default()
+        };
+        let (mappings_tx, mappings_rx) = watch::channel(Vec::new());
+        let (reports_tx, mut reports_rx) = mpsc::channel(16);
+        spawn_prober(mappings_rx, config, reports_tx);
+
+        let port = server(200, Duration::ZERO).await;
+        mappings_tx.send(vec![mapping(port, None)]).unwrap();
+        let report = tokio::time::timeout(Duration::from_secs(2), reports_rx.recv())
+            .await
+            .expect("new mapping was not probed")
+            .unwrap();
+        assert_eq!(report.port, port);
+
+        // A status update alone doesn't start another round
+        mappings_tx.send_modify(|m| m[0].status = report.probe.status.clone());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), reports_rx.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_starting_until_first_success() {
+        let timeout = Duration::from_secs(1);
+        let port = closed_port().await;
+        let mut m = mapping(port, Some("/healthz"));
+        assert_eq!(probe(&m, timeout).await.status, MappingStatus::Starting);
+
+        // Once it has been up, going away is reported as such
+        m.status = MappingStatus::Healthy;
+        assert_eq!(probe(&m, timeout).await.status, MappingStatus::PortUnreachable);
+
+        let port = server(503, Duration::ZERO).await;
+        let mut m = mapping(port, Some("/healthz"));
+        assert_eq!(probe(&m, timeout).await.status, MappingStatus::Starting);
+        m.status = MappingStatus::Healthy;
+        assert_eq!(probe(&m, timeout).await.status, MappingStatus::Unhealthy(503));
+    }
+}
@@ -1,9 +1,14 @@
-use crate::app::Mapping;
+use crate::app::{find_mapping, Mapping};
+use crate::proxy::metrics::ProxyMetrics;
+use anyhow::{Context, Result};
 use http_body_util::{BodyExt, Full};
 use hyper::body::{Bytes, Incoming};
 use hyper::{Request, Response, StatusCode};
 use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::watch;
 
 /// Headers that must not be forwarded between hops (RFC 2616 §13.5.1).
@@ -18,6 +23,29 @@ const HOP_BY_HOP: &[&str] = &[
     "upgrade",
 ];
 
+/// Served for disabled mappings unless `[proxy] disabled_page` is set.
+const DEFAULT_DISABLED_PAGE: &str = "<!DOCTYPE html>
+<html>
+<head><title>{domain} is disabled</title></head>
+<body>
+<h1>{domain} is disabled</h1>
+<p>This mapping is turned off in portmap. Re-enable it to reach the app again.</p>
+</body>
+</html>
+";
+
+/// The disabled-mapping page template: the file at `path`, or the built-in one.
+pub fn load_disabled_page(path: Option<&Path>) -> Result<Arc<str>> {
+    match path {
+        Some(path) => {
+            let page = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            Ok(page.into())
+        }
+        None => Ok(DEFAULT_DISABLED_PAGE.into()),
+    }
+}
+
 type BoxBody = http_body_util::combinators::BoxBody<Bytes, hyper::Error>;
 
 fn full_body(s: &str) -> BoxBody {
@@ -30,6 +58,8 @@ fn full_body(s: &str) -> BoxBody {
 pub async fn handle_request(
     req: Request<Incoming>,
     mappings_rx: watch::Receiver<Vec<Mapping>>,
+    disabled_page: Arc<str>,
+    metrics: Arc<ProxyMetrics>,
 ) -> Result<Response<BoxBody>, hyper::Error> {
     // Extract host from the Host header
     let host = req
@@ -51,12 +81,19 @@ pub async fn handle_request(
         }
     };
 
-    // Look up the mapping
+    // Look up the mapping (subdomains route to their parent mapping)
     let mappings = mappings_rx.borrow().clone();
-    let mapping = mappings.iter().find(|m| m.domain == host);
+    let mapping = find_mapping(&mappings, &host);
 
-    let port = match mapping {
-        Some(m) => m.port,
+    let (domain, port) = match mapping {
+        Some(m) if !m.enabled => {
+            return Ok(Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(hyper::header::CONTENT_TYPE, "text/html; charset=utf-8")
+                .body(full_body(&disabled_page.replace("{domain}", &m.domain)))
+                .unwrap());
+        }
+        Some(m) => (m.domain.clone(), m.port),
         None => {
             return Ok(Response::builder()
                 .status(StatusCode::NOT_FOUND)
@@ -104,7 +141,14 @@ pub async fn handle_request(
     let client: Client<_, Incoming> =
         Client::builder(TokioExecutor::new()).build_http();
 
-    match client.request(forwarded_req).await {
+    let started = Instant::now();
+    let result = client.request(forwarded_req).await;
+    let failed = result
+        .as_ref()
+        .map_or(true, |resp| resp.status().is_server_error());
+    metrics.record(&domain, started.elapsed(), failed);
+
+    match result {
         Ok(resp) => {
             // Strip hop-by-hop headers from response
             let (parts, body) = resp.into_parts();
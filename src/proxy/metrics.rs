@@ -0,0 +1,202 @@
+//! Per-mapping request counters, kept by the proxy and shown in the TUI's
+//! detail pane.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many seconds of requests-per-second history are kept.
+pub const WINDOW_SECS: usize = 300;
+
+/// Latency percentiles are taken over this many of the latest requests.
+const LATENCY_SAMPLES: usize = 1000;
+
+#[derive(Debug, Default)]
+struct DomainMetrics {
+    requests: u64,
+    errors: u64,
+    latencies: VecDeque<Duration>,
+    /// (second since start, requests in it), oldest first, only non-empty
+    /// seconds within the window
+    per_second: VecDeque<(u64, u64)>,
+}
+
+/// Counters for every mapping that has served a request, by domain.
+#[derive(Debug)]
+pub struct ProxyMetrics {
+    start: Instant,
+    domains: Mutex<HashMap<String, DomainMetrics>>,
+}
+
+/// One mapping's counters at a point in time.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MetricsSnapshot {
+    pub requests: u64,
+    /// Upstream 5xx responses and failed connections
+    pub errors: u64,
+    pub p50: Option<Duration>,
+    pub p95: Option<Duration>,
+    /// Requests in each of the last [`WINDOW_SECS`] seconds, oldest first
+    pub per_second: Vec<u64>,
+}
+
+impl MetricsSnapshot {
+    /// Share of requests that failed, 0.0 to 1.0.
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+}
+
+impl Default for ProxyMetrics {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            domains: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ProxyMetrics {
+    /// Count a proxied request to `domain` that took `latency` to get a
+    /// response (or fail).
+    pub fn record(&self, domain: &str, latency: Duration, error: bool) {
+        self.record_at(domain, latency, error, Instant::now());
+    }
+
+    fn record_at(&self, domain: &str, latency: Duration, error: bool, now: Instant) {
+        let second = self.second(now);
+        let mut domains = self.domains.lock().unwrap();
+        let metrics = domains.entry(domain.to_string()).or_default();
+        metrics.requests += 1;
+        if error {
+            metrics.errors += 1;
+        }
+        if metrics.latencies.len() == LATENCY_SAMPLES {
+            metrics.latencies.pop_front();
+        }
+        metrics.latencies.push_back(latency);
+        match metrics.per_second.back_mut() {
+            // A request that raced a later one into the lock counts with it
+            Some((s, count)) if *s >= second => *count += 1,
+            _ => metrics.per_second.push_back((second, 1)),
+        }
+        while metrics
+            .per_second
+            .front()
+            .is_some_and(|(s, _)| second.saturating_sub(*s) >= WINDOW_SECS as u64)
+        {
+            metrics.per_second.pop_front();
+        }
+    }
+
+    pub fn snapshot(&self, domain: &str) -> MetricsSnapshot {
+        self.snapshot_at(domain, Instant::now())
+    }
+
+    fn snapshot_at(&self, domain: &str, now: Instant) -> MetricsSnapshot {
+        let mut per_second = vec![0; WINDOW_SECS];
+        let domains = self.domains.lock().unwrap();
+        let Some(metrics) = domains.get(domain) else {
+            return MetricsSnapshot {
+                per_second,
+                ..Default::default()
+            };
+        };
+
+        let second = self.second(now);
+        for (s, count) in &metrics.per_second {
+            let age = second.saturating_sub(*s) as usize;
+            if age < WINDOW_SECS {
+                per_second[WINDOW_SECS - 1 - age] = *count;
+            }
+        }
+        let mut latencies: Vec<Duration> = metrics.latencies.iter().copied().collect();
+        latencies.sort();
+        MetricsSnapshot {
+            requests: metrics.requests,
+            errors: metrics.errors,
+            p50: percentile(&latencies, 50),
+            p95: percentile(&latencies, 95),
+            per_second,
+        }
+    }
+
+    fn second(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_secs()
+    }
+}
+
+/// Nearest-rank percentile of sorted samples.
+fn percentile(sorted: &[Duration], p: usize) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_counts_and_percentiles() {
+        let metrics = ProxyMetrics::default();
+        let now = metrics.start;
+        for n in 1..=100 {
+            metrics.record_at("api.localhost", ms(n), n > 98, now);
+        }
+        metrics.record_at("web.localhost", ms(5), false, now);
+
+        let snapshot = metrics.snapshot_at("api.localhost", now);
+        assert_eq!(snapshot.requests, 100);
+        assert_eq!(snapshot.errors, 2);
+        assert_eq!(snapshot.error_rate(), 0.02);
+        assert_eq!(snapshot.p50, Some(ms(50)));
+        assert_eq!(snapshot.p95, Some(ms(95)));
+
+        let empty = metrics.snapshot_at("other.localhost", now);
+        assert_eq!((empty.requests, empty.p50, empty.error_rate()), (0, None, 0.0));
+        assert_eq!(empty.per_second.len(), WINDOW_SECS);
+    }
+
+    #[test]
+    fn test_requests_per_second_window() {
+        let metrics = ProxyMetrics::default();
+        let at = |secs| metrics.start + Duration::from_secs(secs);
+        metrics.record_at("api.localhost", ms(1), false, at(0));
+        metrics.record_at("api.localhost", ms(1), false, at(10));
+        metrics.record_at("api.localhost", ms(1), false, at(10));
+        metrics.record_at("api.localhost", ms(1), false, at(12));
+
+        let rps = metrics.snapshot_at("api.localhost", at(12)).per_second;
+        assert_eq!(rps[WINDOW_SECS - 1], 1);
+        assert_eq!(rps[WINDOW_SECS - 3], 2);
+        assert_eq!(rps[WINDOW_SECS - 13], 1);
+        assert_eq!(rps.iter().sum::<u64>(), 4);
+
+        // Old seconds fall out of the window, but still count as requests
+        let later = at(12 + WINDOW_SECS as u64);
+        metrics.record_at("api.localhost", ms(1), false, later);
+        let snapshot = metrics.snapshot_at("api.localhost", later);
+        assert_eq!(snapshot.per_second.iter().sum::<u64>(), 1);
+        assert_eq!(snapshot.requests, 5);
+        assert_eq!(metrics.domains.lock().unwrap()["api.localhost"].per_second.len(), 1);
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[], 50), None);
+        assert_eq!(percentile(&[ms(7)], 95), Some(ms(7)));
+        assert_eq!(percentile(&[ms(1), ms(2), ms(3), ms(4)], 50), Some(ms(2)));
+        assert_eq!(percentile(&[ms(1), ms(2), ms(3), ms(4)], 95), Some(ms(4)));
+    }
+}
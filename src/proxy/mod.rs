@@ -1,2 +1,3 @@
 pub mod handler;
+pub mod metrics;
 pub mod server;
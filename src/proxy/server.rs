@@ -1,46 +1,175 @@
 use crate::app::Mapping;
 use crate::proxy::handler::handle_request;
+use crate::proxy::metrics::ProxyMetrics;
 use anyhow::Result;
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper_util::rt::TokioIo;
-use std::net::SocketAddr;
-use tokio::net::TcpListener;
-use tokio::sync::watch;
+use hyper_util::server::graceful::GracefulShutdown;
+use socket2::{Domain, Socket, Type};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
 
-/// Start the reverse proxy server on port 80.
-/// Runs until the shutdown signal is received.
+/// Pause after a failed accept, so e.g. running out of file descriptors
+/// doesn't spin the acceptor.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Bind a listener, keeping IPv6 sockets v6-only so an IPv4 listener on the
+/// same port can coexist with it.
+fn bind_listener(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Resolve listen specs (`127.0.0.1:80`, `[::1]:80`, or a bare address
+/// that takes `port`) into socket addresses. No specs means loopback only,
+/// on both address families.
+pub fn listen_addrs(specs: &[String], port: u16) -> Result<Vec<SocketAddr>> {
+    if specs.is_empty() {
+        return Ok(vec![
+            SocketAddr::from(([127, 0, 0, 1], port)),
+            SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], port)),
+        ]);
+    }
+    specs
+        .iter()
+        .map(|spec| {
+            if let Ok(addr) = spec.parse::<SocketAddr>() {
+                return Ok(addr);
+            }
+            let ip = spec.trim_start_matches('[').trim_end_matches(']');
+            ip.parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, port))
+                .map_err(|_| anyhow::anyhow!("Invalid listen address: {}", spec))
+        })
+        .collect()
+}
+
+/// Bind a listener on each address.
+///
+/// Done up front, while still root, so privileges can be dropped before
+/// any connection is served. Addresses that can't be bound (e.g. `::1` on
+/// a host without IPv6) are skipped as long as at least one succeeds.
+pub fn bind_listeners(addrs: &[SocketAddr]) -> Result<Vec<TcpListener>> {
+    let mut listeners = Vec::new();
+    let mut last_err = None;
+    for &addr in addrs {
+        match bind_listener(addr) {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => last_err = Some((addr, e)),
+        }
+    }
+    if listeners.is_empty() {
+        let (addr, err) = last_err.expect("at least one listen address");
+        let hint = if addr.port() < 1024 {
+            " Are you running with sudo?"
+        } else {
+            ""
+        };
+        return Err(anyhow::anyhow!(
+            "Failed to bind to {}: {}.{}",
+            addr,
+            err,
+            hint
+        ));
+    }
+    Ok(listeners)
+}
+
+/// Where the proxy is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ProxyPhase {
+    #[default]
+    Running,
+    /// Shutdown requested; waiting for in-flight connections
+    Draining,
+    Stopped,
+}
+
+/// Live proxy state, published for the TUI.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ProxyStatus {
+    pub phase: ProxyPhase,
+    /// Open client connections
+    pub active_connections: usize,
+}
+
+/// Run the reverse proxy on already-bound listeners.
+///
+/// When the shutdown signal is received, listeners close, open connections
+/// get hyper's graceful-shutdown signal (finish the current request, then
+/// close), and anything still running after `drain_timeout` is aborted.
 pub async fn run_proxy(
+    listeners: Vec<TcpListener>,
     mappings_rx: watch::Receiver<Vec<Mapping>>,
     mut shutdown_rx: watch::Receiver<bool>,
+    drain_timeout: Duration,
+    status_tx: watch::Sender<ProxyStatus>,
+    disabled_page: Arc<str>,
+    metrics: Arc<ProxyMetrics>,
 ) -> Result<()> {
-    let addr = SocketAddr::from(([0, 0, 0, 0], 80));
-    let listener = TcpListener::bind(addr).await.map_err(|e| {
-        anyhow::anyhow!(
-            "Failed to bind to port 80: {}. Are you running with sudo?",
-            e
-        )
-    })?;
+    // One acceptor per listener, all feeding a single serve loop
+    let (stream_tx, mut stream_rx) = mpsc::channel::<TcpStream>(64);
+    let mut acceptors = JoinSet::new();
+    for listener in listeners {
+        let tx = stream_tx.clone();
+        acceptors.spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _addr)) => stream,
+                    Err(e) => {
+                        // Failing here would skip the drain for every
+                        // open connection; wait and try again instead
+                        eprintln!("Proxy accept error: {}", e);
+                        tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                        continue;
+                    }
+                };
+                if tx.send(stream).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    drop(stream_tx);
+
+    let graceful = GracefulShutdown::new();
+    let mut connections = JoinSet::new();
 
     loop {
         tokio::select! {
-            result = listener.accept() => {
-                let (stream, _addr) = result?;
+            Some(stream) = stream_rx.recv() => {
                 let rx = mappings_rx.clone();
-                tokio::spawn(async move {
-                    let io = TokioIo::new(stream);
-                    let service = service_fn(move |req| {
-                        let rx = rx.clone();
-                        handle_request(req, rx)
-                    });
-                    if let Err(e) = http1::Builder::new()
-                        .serve_connection(io, service)
-                        .await
-                    {
+                let page = disabled_page.clone();
+                let metrics = metrics.clone();
+                let io = TokioIo::new(stream);
+                let service = service_fn(move |req| {
+                    let rx = rx.clone();
+                    handle_request(req, rx, page.clone(), metrics.clone())
+                });
+                let conn = graceful.watch(http1::Builder::new().serve_connection(io, service));
+                status_tx.send_modify(|s| s.active_connections += 1);
+                let status = status_tx.clone();
+                connections.spawn(async move {
+                    if let Err(e) = conn.await {
                         eprintln!("Connection error: {}", e);
                     }
+                    status.send_modify(|s| s.active_connections -= 1);
                 });
             }
+            // Reap finished connection tasks
+            Some(_) = connections.join_next() => {}
             _ = shutdown_rx.changed() => {
                 if *shutdown_rx.borrow() {
                     break;
@@ -49,5 +178,257 @@ pub async fn run_proxy(
         }
     }
 
+    // Stop accepting, then drain
+    acceptors.abort_all();
+    status_tx.send_modify(|s| s.phase = ProxyPhase::Draining);
+    if tokio::time::timeout(drain_timeout, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        connections.abort_all();
+    }
+    while connections.join_next().await.is_some() {}
+    status_tx.send_modify(|s| {
+        s.phase = ProxyPhase::Stopped;
+        s.active_connections = 0;
+    });
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_listen_addrs_are_loopback() {
+        let addrs = listen_addrs(&[], 80).unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert!(addrs.iter().all(|a| a.ip().is_loopback() && a.port() == 80));
+    }
+
+    #[test]
+    fn test_listen_specs() {
+        let specs: Vec<String> = ["127.0.0.1:8080", "[::1]:80", "192.168.1.5", "[fe80::1]"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let addrs = listen_addrs(&specs, 9000).unwrap();
+        assert_eq!(addrs[0], SocketAddr::from(([127, 0, 0, 1], 8080)));
+        assert_eq!(addrs[1], "[::1]:80".parse().unwrap());
+        assert_eq!(addrs[2], SocketAddr::from(([192, 168, 1, 5], 9000)));
+        assert_eq!(addrs[3], "[fe80::1]:9000".parse().unwrap());
+
+        assert!(listen_addrs(&["localhost:80".to_string()], 80).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drains_in_flight_request_on_shutdown() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Upstream that answers slowly, like a long streaming response
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\ndone")
+                .await
+                .unwrap();
+        });
+
+        let listeners = bind_listeners(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+        let proxy_addr = listeners[0].local_addr().unwrap();
+        let mapping = Mapping::new("slow.localhost".to_string(), upstream_port);
+        let (_mappings_tx, mappings_rx) = watch::channel(vec![mapping]);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let (status_tx, mut status_rx) = watch::channel(ProxyStatus::default());
+        let metrics = Arc::new(ProxyMetrics::default());
+        let proxy = tokio::spawn(run_proxy(
+            listeners,
+            mappings_rx,
+            shutdown_rx,
+            Duration::from_secs(5),
+            status_tx,
+            "disabled".into(),
+            metrics.clone(),
+        ));
+
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: slow.localhost\r\n\r\n")
+            .await
+            .unwrap();
+        status_rx
+            .wait_for(|s| s.active_connections == 1)
+            .await
+            .unwrap();
+
+        // Shut down while the response is still in flight
+        shutdown_tx.send(true).unwrap();
+        status_rx
+            .wait_for(|s| s.phase == ProxyPhase::Draining)
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("done"));
+        let snapshot = metrics.snapshot("slow.localhost");
+        assert_eq!((snapshot.requests, snapshot.errors), (1, 0));
+        assert!(snapshot.p50.unwrap() >= Duration::from_millis(300));
+
+        proxy.await.unwrap().unwrap();
+        assert_eq!(
+            *status_rx.borrow(),
+            ProxyStatus {
+                phase: ProxyPhase::Stopped,
+                active_connections: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accept_error_keeps_serving() {
+        use std::os::fd::AsRawFd;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        let (respond_tx, respond_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            let _ = respond_rx.await;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\ndone")
+                .await
+                .unwrap();
+        });
+
+        let addrs = ["127.0.0.1:0".parse().unwrap(), "127.0.0.1:0".parse().unwrap()];
+        let listeners = bind_listeners(&addrs).unwrap();
+        let proxy_addr = listeners[0].local_addr().unwrap();
+        let broken_fd = listeners[1].as_raw_fd();
+        let mapping = Mapping::new("slow.localhost".to_string(), upstream_port);
+        let (_mappings_tx, mappings_rx) = watch::channel(vec![mapping]);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let (status_tx, mut status_rx) = watch::channel(ProxyStatus::default());
+        let proxy = tokio::spawn(run_proxy(
+            listeners,
+            mappings_rx,
+            shutdown_rx,
+            Duration::from_secs(5),
+            status_tx,
+            "disabled".into(),
+            Arc::default(),
+        ));
+
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: slow.localhost\r\n\r\n")
+            .await
+            .unwrap();
+        status_rx
+            .wait_for(|s| s.active_connections == 1)
+            .await
+            .unwrap();
+
+        // The second listener starts failing every accept
+        assert_eq!(unsafe { libc::shutdown(broken_fd, libc::SHUT_RD) }, 0);
+        tokio::time::sleep(ACCEPT_ERROR_DELAY * 3).await;
+        assert!(!proxy.is_finished());
+
+        respond_tx.send(()).unwrap();
+        shutdown_tx.send(true).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("done"));
+        proxy.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout_aborts_connections() {
+        let listeners = bind_listeners(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+        let proxy_addr = listeners[0].local_addr().unwrap();
+        let (_mappings_tx, mappings_rx) = watch::channel(Vec::new());
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let (status_tx, mut status_rx) = watch::channel(ProxyStatus::default());
+        let proxy = tokio::spawn(run_proxy(
+            listeners,
+            mappings_rx,
+            shutdown_rx,
+            Duration::from_millis(100),
+            status_tx,
+            "disabled".into(),
+            Arc::default(),
+        ));
+
+        // A client that sends half a request and then stalls
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        tokio::io::AsyncWriteExt::write_all(&mut client, b"GET / HTTP/1.1\r\n")
+            .await
+            .unwrap();
+        status_rx
+            .wait_for(|s| s.active_connections == 1)
+            .await
+            .unwrap();
+
+        shutdown_tx.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), proxy)
+            .await
+            .expect("drain timeout not enforced")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_disabled_mapping_serves_disabled_page() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listeners = bind_listeners(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+        let proxy_addr = listeners[0].local_addr().unwrap();
+        let mut mapping = Mapping::new("off.localhost".to_string(), 1);
+        mapping.enabled = false;
+        let (_mappings_tx, mappings_rx) = watch::channel(vec![mapping]);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let (status_tx, _status_rx) = watch::channel(ProxyStatus::default());
+        let metrics = Arc::new(ProxyMetrics::default());
+        tokio::spawn(run_proxy(
+            listeners,
+            mappings_rx,
+            shutdown_rx,
+            Duration::from_secs(1),
+            status_tx,
+            "<p>{domain} is off</p>".into(),
+            metrics.clone(),
+        ));
+
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: api.off.localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"));
+        assert!(response.contains("text/html"));
+        assert!(response.ends_with("<p>off.localhost is off</p>"));
+        // Nothing was proxied
+        assert_eq!(metrics.snapshot("off.localhost").requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_bind_multiple_listeners() {
+        let addrs = listen_addrs(&["127.0.0.1:0".to_string(), "127.0.0.1:0".to_string()], 0)
+            .unwrap();
+        let listeners = bind_listeners(&addrs).unwrap();
+        assert_eq!(listeners.len(), 2);
+    }
+}
@@ -0,0 +1,126 @@
+//! Opening mappings in the browser and copying their URLs.
+
+use crate::config::user_home;
+use crate::privilege::{is_root, InvokingUser};
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+/// The URL a mapping is reached at through the proxy.
+pub fn mapping_url(domain: &str, listen: &[SocketAddr]) -> String {
+    match listen.first().map(|a| a.port()) {
+        Some(80) | None => format!("http://{}/", domain),
+        Some(port) => format!("http://{}:{}/", domain, port),
+    }
+}
+
+/// The command that opens `url`: the first entry of `$BROWSER` (where
+/// `%s` stands for the URL, else it's appended), or the system opener.
+fn opener_command(browser: Option<&str>, url: &str) -> Vec<String> {
+    let browser = browser
+        .and_then(|b| b.split(':').map(str::trim).find(|b| !b.is_empty()));
+    let Some(browser) = browser else {
+        let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+        return vec![opener.to_string(), url.to_string()];
+    };
+    let mut argv: Vec<String> = browser
+        .split_whitespace()
+        .map(|arg| arg.replace("%s", url))
+        .collect();
+    if !browser.contains("%s") {
+        argv.push(url.to_string());
+    }
+    argv
+}
+
+/// Open `url` in the browser without waiting for it.
+///
+/// Under sudo the browser runs as the invoking user, with their home
+/// directory, so it uses their profile rather than root's.
+pub fn open_url(url: &str) -> Result<()> {
+    let argv = opener_command(std::env::var("BROWSER").ok().as_deref(), url);
+    let mut command = Command::new(&argv[0]);
+    command
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if is_root() {
+        if let Some(user) = InvokingUser::from_env() {
+            command.uid(user.uid).gid(user.gid);
+            if let Some(home) = user_home() {
+                command.env("HOME", home);
+            }
+            command.env("USER", &user.name).env("LOGNAME", &user.name);
+        }
+    }
+    command
+        .spawn()
+        .with_context(|| format!("Failed to run {}", argv[0]))?;
+    Ok(())
+}
+
+/// OSC 52 sequence that sets the clipboard to `text`, wrapped for tmux's
+/// passthrough when inside it.
+fn osc52(text: &str, tmux: bool) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let sequence = format!("\x1b]52;c;{}\x07", encoded);
+    if tmux {
+        // tmux wants escapes inside the passthrough doubled
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+/// Copy `text` to the clipboard of the terminal we're running in, which
+/// also works over SSH.
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    let tmux = std::env::var_os("TMUX").is_some();
+    let mut stdout = std::io::stdout();
+    stdout.write_all(osc52(text, tmux).as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapping_url() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        assert_eq!(
+            mapping_url("api.localhost", &[addr("127.0.0.1:80")]),
+            "http://api.localhost/"
+        );
+        assert_eq!(
+            mapping_url("api.localhost", &[addr("127.0.0.1:8080"), addr("[::1]:8080")]),
+            "http://api.localhost:8080/"
+        );
+    }
+
+    #[test]
+    fn test_opener_command() {
+        let url = "http://api.localhost/";
+        assert_eq!(opener_command(Some("firefox"), url), ["firefox", url]);
+        assert_eq!(
+            opener_command(Some("w3m -o x=1 %s:firefox"), url),
+            ["w3m", "-o", "x=1", url]
+        );
+        assert_eq!(opener_command(Some(""), url).len(), 2);
+        assert_eq!(opener_command(None, url)[1], url);
+    }
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(osc52("hi", false), "\x1b]52;c;aGk=\x07");
+        assert_eq!(
+            osc52("hi", true),
+            "\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\"
+        );
+    }
+}
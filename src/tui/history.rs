@@ -0,0 +1,367 @@
+//! Changes to the mapping list, and undo/redo over them.
+//!
+//! Every edit made from the TUI (add, delete, edit, enable/disable) is a
+//! [`Change`] that knows how to apply itself to both the hosts backend and
+//! the mappings channel, and how to produce its inverse.
+
+use crate::app::{Mapping, MappingStatus};
+use crate::hosts::backend::HostsBackend;
+use tokio::sync::watch;
+
+/// How many changes can be undone.
+const LIMIT: usize = 100;
+
+/// Where changes are applied.
+pub struct Target<'a> {
+    /// Mapping list shared with the proxy, prober and hosts watcher
+    pub mappings: &'a watch::Sender<Vec<Mapping>>,
+    pub hosts: &'a dyn HostsBackend,
+    /// `[hosts] comment_out_conflicts`
+    pub comment_out_conflicts: bool,
+}
+
+/// A reversible change to the mapping list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Add a mapping at this position
+    Insert(usize, Mapping),
+    /// Remove a mapping (by domain); the position is where undo puts it back
+    Remove(usize, Mapping),
+    /// Replace the mapping for `before.domain` with `after`
+    Replace { before: Mapping, after: Mapping },
+}
+
+impl Change {
+    pub fn inverse(&self) -> Change {
+        match self {
+            Change::Insert(index, mapping) => Change::Remove(*index, mapping.clone()),
+            Change::Remove(index, mapping) => Change::Insert(*index, mapping.clone()),
+            Change::Replace { before, after } => Change::Replace {
+                before: after.clone(),
+                after: before.clone(),
+            },
+        }
+    }
+
+    /// Apply the change. Returns a message for the status bar.
+    pub fn apply(&self, target: &Target) -> Result<String, String> {
+        let mut mappings = target.mappings.borrow().clone();
+        match self {
+            Change::Insert(index, mapping) => {
+                // Backends without their own bookkeeping can't detect duplicates
+                if mappings.iter().any(|m| m.domain == mapping.domain) {
+                    return Err("Mapping already exists".to_string());
+                }
+                let mut message = format!("Added {} \u{2192} :{}", mapping.domain, mapping.port);
+                if mapping.enabled {
+                    add_entry(target.hosts, &mapping.domain)?;
+                    if let Some(warning) =
+                        check_conflicts(target.hosts, &mapping.domain, target.comment_out_conflicts)
+                    {
+                        message = warning;
+                    }
+                }
+                let mut mapping = mapping.clone();
+                mapping.status = MappingStatus::Unknown;
+                mapping.latency = None;
+                mappings.insert((*index).min(mappings.len()), mapping);
+                publish(target, mappings)?;
+                Ok(message)
+            }
+            Change::Remove(_, mapping) => {
+                let index = position(&mappings, &mapping.domain)?;
+                let previous = mappings.clone();
+                let removed = mappings.remove(index);
+                // Publish first so the hosts watcher doesn't re-add it
+                publish(target, mappings)?;
+                if removed.enabled {
+                    if let Err(e) = target.hosts.remove_entry(&removed.domain) {
+                        // Still resolves, so keep it listed
+                        publish(target, previous)?;
+                        return Err(format!("Error: {}", e));
+                    }
+                }
+                Ok(format!("Removed {}", removed.domain))
+            }
+            Change::Replace { before, after } => {
+                let index = position(&mappings, &before.domain)?;
+                let renamed = after.domain != before.domain;
+                if renamed && mappings.iter().any(|m| m.domain == after.domain) {
+                    return Err("Mapping already exists".to_string());
+                }
+
+                // Same target: the last probe result still holds
+                let current = &mappings[index];
+                let mut replacement = after.clone();
+                if current.port == after.port && current.health == after.health {
+                    replacement.status = current.status.clone();
+                    replacement.latency = current.latency;
+                } else {
+                    replacement.status = MappingStatus::Unknown;
+                    replacement.latency = None;
+                }
+                let was_enabled = current.enabled;
+
+                let mut message = match (was_enabled, after.enabled) {
+                    (false, true) => format!("Enabled {}", after.domain),
+                    (true, false) => format!("Disabled {}", after.domain),
+                    _ => format!("Updated {} \u{2192} :{}", after.domain, after.port),
+                };
+                // Make the new name resolve before anything routes to it
+                if after.enabled {
+                    if was_enabled && renamed {
+                        match target.hosts.rename_entry(&before.domain, &after.domain) {
+                            Ok(true) => {}
+                            Ok(false) => return Err("Mapping already exists".to_string()),
+                            Err(e) => return Err(format!("Error: {}", e)),
+                        }
+                    } else if !was_enabled {
+                        add_entry(target.hosts, &after.domain)?;
+                    }
+                    if renamed {
+                        if let Some(warning) = check_conflicts(
+                            target.hosts,
+                            &after.domain,
+                            target.comment_out_conflicts,
+                        ) {
+                            message = warning;
+                        }
+                    }
+                }
+                mappings[index] = replacement;
+                publish(target, mappings)?;
+                if was_enabled && !after.enabled {
+                    let _ = target.hosts.remove_entry(&before.domain);
+                }
+                Ok(message)
+            }
+        }
+    }
+}
+
+fn position(mappings: &[Mapping], domain: &str) -> Result<usize, String> {
+    mappings
+        .iter()
+        .position(|m| m.domain == domain)
+        .ok_or_else(|| format!("{} no longer exists", domain))
+}
+
+fn add_entry(hosts: &dyn HostsBackend, domain: &str) -> Result<(), String> {
+    match hosts.add_entry(domain) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("Mapping already exists".to_string()),
+        Err(e) => Err(format!("Error: {}", e)),
+    }
+}
+
+fn publish(target: &Target, mappings: Vec<Mapping>) -> Result<(), String> {
+    target
+        .mappings
+        .send(mappings)
+        .map_err(|e| format!("Error: {}", e))
+}
+
+/// Look for /etc/hosts entries outside our block that shadow `domain`,
+/// optionally commenting them out. Returns a warning for the status bar.
+fn check_conflicts(hosts: &dyn HostsBackend, domain: &str, comment_out: bool) -> Option<String> {
+    let result = if comment_out {
+        hosts.disable_conflicts(domain)
+    } else {
+        hosts.conflicts(domain)
+    };
+    let conflicts = match result {
+        Ok(c) if c.is_empty() => return None,
+        Ok(c) => c,
+        Err(e) => return Some(format!("Error checking {}: {}", hosts.describe(), e)),
+    };
+
+    let lines = conflicts
+        .iter()
+        .map(|c| format!("{} ({})", c.line, c.ip))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(if comment_out {
+        format!("Added {}; commented out conflicting /etc/hosts line {}", domain, lines)
+    } else {
+        format!("Warning: {} is also mapped in /etc/hosts at line {}", domain, lines)
+    })
+}
+
+/// Applied changes that can be undone, and undone ones that can be redone.
+#[derive(Default)]
+pub struct History {
+    done: Vec<Change>,
+    undone: Vec<Change>,
+}
+
+impl History {
+    /// Apply `change` and remember it. A new change clears the redo stack.
+    pub fn perform(&mut self, change: Change, target: &Target) -> Result<String, String> {
+        let message = change.apply(target)?;
+        self.done.push(change);
+        if self.done.len() > LIMIT {
+            self.done.remove(0);
+        }
+        self.undone.clear();
+        Ok(message)
+    }
+
+    pub fn undo(&mut self, target: &Target) -> Result<String, String> {
+        let change = self.done.pop().ok_or("Nothing to undo")?;
+        match change.inverse().apply(target) {
+            Ok(message) => {
+                self.undone.push(change);
+                Ok(format!("Undo: {}", message))
+            }
+            Err(e) => {
+                self.done.push(change);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn redo(&mut self, target: &Target) -> Result<String, String> {
+        let change = self.undone.pop().ok_or("Nothing to redo")?;
+        match change.apply(target) {
+            Ok(message) => {
+                self.done.push(change);
+                Ok(format!("Redo: {}", message))
+            }
+            Err(e) => {
+                self.undone.push(change);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hosts::manager::HostsManager;
+    use tempfile::NamedTempFile;
+
+    fn hosts_content(hosts: &HostsManager) -> String {
+        std::fs::read_to_string(hosts.path()).unwrap()
+    }
+
+    fn domains(tx: &watch::Sender<Vec<Mapping>>) -> Vec<String> {
+        tx.borrow().iter().map(|m| m.domain.clone()).collect()
+    }
+
+    #[test]
+    fn test_undo_redo_delete() {
+        let file = NamedTempFile::new().unwrap();
+        let hosts = HostsManager::with_path(file.path().to_path_buf());
+        let (tx, _rx) = watch::channel(Vec::new());
+        let target = Target {
+            mappings: &tx,
+            hosts: &hosts,
+            comment_out_conflicts: false,
+        };
+        let mut history = History::default();
+
+        let a = Mapping::new("a.localhost".to_string(), 3000);
+        let b = Mapping::new("b.localhost".to_string(), 4000);
+        history.perform(Change::Insert(0, a.clone()), &target).unwrap();
+        history.perform(Change::Insert(1, b), &target).unwrap();
+        history.perform(Change::Remove(0, a), &target).unwrap();
+        assert_eq!(domains(&tx), vec!["b.localhost"]);
+        assert!(!hosts_content(&hosts).contains("a.localhost"));
+
+        // Undo puts it back where it was, hosts entry included
+        assert_eq!(history.undo(&target).unwrap(), "Undo: Added a.localhost \u{2192} :3000");
+        assert_eq!(domains(&tx), vec!["a.localhost", "b.localhost"]);
+        assert!(hosts_content(&hosts).contains("a.localhost"));
+
+        history.redo(&target).unwrap();
+        assert_eq!(domains(&tx), vec!["b.localhost"]);
+        assert!(history.redo(&target).is_err());
+    }
+
+    #[test]
+    fn test_undo_edit_and_toggle() {
+        let file = NamedTempFile::new().unwrap();
+        let hosts = HostsManager::with_path(file.path().to_path_buf());
+        let (tx, _rx) = watch::channel(Vec::new());
+        let target = Target {
+            mappings: &tx,
+            hosts: &hosts,
+            comment_out_conflicts: false,
+        };
+        let mut history = History::default();
+
+        let original = Mapping::new("a.localhost".to_string(), 3000);
+        history.perform(Change::Insert(0, original.clone()), &target).unwrap();
+
+        let renamed = Mapping::new("b.localhost".to_string(), 3001);
+        history
+            .perform(
+                Change::Replace {
+                    before: original.clone(),
+                    after: renamed.clone(),
+                },
+                &target,
+            )
+            .unwrap();
+        let mut disabled = renamed.clone();
+        disabled.enabled = false;
+        history
+            .perform(
+                Change::Replace {
+                    before: renamed,
+                    after: disabled,
+                },
+                &target,
+            )
+            .unwrap();
+        assert!(!tx.borrow()[0].enabled);
+        assert!(!hosts_content(&hosts).contains(".localhost"));
+
+        history.undo(&target).unwrap();
+        assert!(tx.borrow()[0].enabled);
+        assert!(hosts_content(&hosts).contains("b.localhost"));
+
+        history.undo(&target).unwrap();
+        assert_eq!(tx.borrow()[0].port, 3000);
+        let content = hosts_content(&hosts);
+        assert!(content.contains("a.localhost"));
+        assert!(!content.contains("b.localhost"));
+
+        // A new change drops what could have been redone
+        history.perform(Change::Remove(0, original), &target).unwrap();
+        assert!(history.redo(&target).is_err());
+    }
+
+    #[test]
+    fn test_failed_remove_is_not_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hosts");
+        std::fs::write(&path, "").unwrap();
+        let hosts = HostsManager::with_path(path.clone());
+        let (tx, _rx) = watch::channel(Vec::new());
+        let target = Target {
+            mappings: &tx,
+            hosts: &hosts,
+            comment_out_conflicts: false,
+        };
+        let mut history = History::default();
+
+        let a = Mapping::new("a.localhost".to_string(), 3000);
+        history.perform(Change::Insert(0, a.clone()), &target).unwrap();
+
+        // Make the hosts file unreadable, even for root
+        std::fs::remove_file(&path).unwrap();
+        std::fs::create_dir(&path).unwrap();
+        assert!(history.perform(Change::Remove(0, a), &target).is_err());
+        assert_eq!(domains(&tx), vec!["a.localhost"]);
+
+        // Undoing the insert fails the same way, and stays undoable
+        assert!(history.undo(&target).is_err());
+        assert_eq!(domains(&tx), vec!["a.localhost"]);
+        std::fs::remove_dir(&path).unwrap();
+        std::fs::write(&path, "").unwrap();
+        history.undo(&target).unwrap();
+        assert!(domains(&tx).is_empty());
+    }
+}
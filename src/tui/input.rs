@@ -1,5 +1,14 @@
-use crate::app::{InputMode, Mapping, MappingStatus, PopupField, TuiState};
-use crossterm::event::{KeyCode, KeyEvent};
+use crate::app::{parse_tags, InputIssue, InputMode, Mapping, PopupField, Severity, TuiState};
+use crate::config::HealthConfig;
+use crate::probe::HealthCheck;
+use crate::tui::keymap::Action;
+use crate::tui::ui::ClickMap;
+use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Position;
+use std::time::{Duration, Instant};
+
+/// Two clicks on the same row within this count as a double-click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
 
 /// Result of processing a key event.
 pub enum InputResult {
@@ -7,53 +16,198 @@ pub enum InputResult {
     Continue,
     /// User wants to quit
     Quit,
+    /// Open this mapping in the browser
+    Open(String),
+    /// Copy this mapping's URL to the clipboard
+    Copy(String),
+}
+
+/// What a mouse event in Normal mode amounts to.
+pub enum MouseAction {
+    /// Nothing (or handled here, like selecting a row)
+    None,
+    /// Same as pressing this key (a clicked key hint)
+    Key(KeyEvent),
+    /// Double-click: open this mapping in the browser
+    Open(String),
 }
 
-/// Process a key event in Normal mode.
-pub fn handle_normal_key(
-    key: KeyEvent,
+/// Process a mouse event in Normal mode, using where things were drawn
+/// in the last frame.
+pub fn handle_mouse(
+    mouse: MouseEvent,
+    state: &mut TuiState,
+    mappings: &[Mapping],
+    clicks: &ClickMap,
+) -> MouseAction {
+    let at = Position::new(mouse.column, mouse.row);
+    match mouse.kind {
+        MouseEventKind::ScrollDown => {
+            handle_action(Action::Down, state, mappings);
+            MouseAction::None
+        }
+        MouseEventKind::ScrollUp => {
+            handle_action(Action::Up, state, mappings);
+            MouseAction::None
+        }
+        MouseEventKind::Down(MouseButton::Left) => {
+            if let Some((_, key)) = clicks.hints.iter().find(|(area, _)| area.contains(at)) {
+                return MouseAction::Key(key.event());
+            }
+            if !clicks.rows.contains(at) {
+                return MouseAction::None;
+            }
+            let rows = state.visible(mappings);
+            let row = clicks.offset + usize::from(mouse.row - clicks.rows.y);
+            let Some(mapping) = rows.get(row) else {
+                return MouseAction::None;
+            };
+            state.select_row(&rows, row);
+
+            let now = Instant::now();
+            let double = state.last_click.as_ref().is_some_and(|(time, domain)| {
+                *domain == mapping.domain && now.duration_since(*time) < DOUBLE_CLICK
+            });
+            if double {
+                state.last_click = None;
+                MouseAction::Open(mapping.domain.clone())
+            } else {
+                state.last_click = Some((now, mapping.domain.clone()));
+                MouseAction::None
+            }
+        }
+        _ => MouseAction::None,
+    }
+}
+
+/// Carry out a Normal mode action.
+pub fn handle_action(
+    action: Action,
     state: &mut TuiState,
     mappings: &[Mapping],
 ) -> InputResult {
-    match key.code {
-        KeyCode::Char('q') => InputResult::Quit,
-        KeyCode::Char('a') => {
+    match action {
+        Action::Quit => InputResult::Quit,
+        Action::Add => {
             state.mode = InputMode::Adding;
             state.domain_input.clear();
             state.port_input.clear();
+            state.health_input.clear();
+            state.tags_input.clear();
             state.popup_field = PopupField::Domain;
             state.status_message = None;
             InputResult::Continue
         }
-        KeyCode::Char('j') | KeyCode::Down => {
-            if !mappings.is_empty() {
-                state.selected = (state.selected + 1).min(mappings.len() - 1);
+        Action::Edit => {
+            if let Some((_, mapping)) = state.selected_mapping(mappings) {
+                state.mode = InputMode::Editing(mapping.domain.clone());
+                state.domain_input = mapping
+                    .domain
+                    .strip_suffix(".localhost")
+                    .unwrap_or(&mapping.domain)
+                    .to_string();
+                state.port_input = mapping.port.to_string();
+                state.health_input = mapping
+                    .health
+                    .as_ref()
+                    .map_or(String::new(), |h| h.to_string());
+                state.tags_input = mapping.tags.join(" ");
+                state.popup_field = PopupField::Port;
+                state.status_message = None;
             }
             InputResult::Continue
         }
-        KeyCode::Char('k') | KeyCode::Up => {
-            state.selected = state.selected.saturating_sub(1);
+        Action::Down => {
+            let rows = state.visible(mappings);
+            state.select_row(&rows, state.selected_row + 1);
             InputResult::Continue
         }
-        KeyCode::Char('d') => {
-            // Delete will be handled by the caller since it needs mutable access to mappings
+        Action::Up => {
+            let rows = state.visible(mappings);
+            state.select_row(&rows, state.selected_row.saturating_sub(1));
             InputResult::Continue
         }
-        _ => InputResult::Continue,
+        Action::Open => match state.selected_mapping(mappings) {
+            Some((_, mapping)) => InputResult::Open(mapping.domain.clone()),
+            None => InputResult::Continue,
+        },
+        Action::Copy => match state.selected_mapping(mappings) {
+            Some((_, mapping)) => InputResult::Copy(mapping.domain.clone()),
+            None => InputResult::Continue,
+        },
+        Action::Filter => {
+            state.mode = InputMode::Filtering;
+            state.status_message = None;
+            InputResult::Continue
+        }
+        Action::Sort => {
+            state.sort = state.sort.next();
+            state.status_message = Some(format!("Sorted by {}", state.sort.label()));
+            InputResult::Continue
+        }
+        Action::Help => {
+            state.show_help = true;
+            InputResult::Continue
+        }
+        Action::Delete | Action::Toggle | Action::Undo | Action::Redo | Action::Update => {
+            // These edit the mapping list or install an update, which the caller does
+            InputResult::Continue
+        }
+    }
+}
+
+/// Process a key event while typing a filter. The table narrows as you
+/// type; Enter keeps the filter, Esc clears it.
+pub fn handle_filter_key(key: KeyEvent, state: &mut TuiState, mappings: &[Mapping]) -> InputResult {
+    match key.code {
+        KeyCode::Esc => {
+            state.filter.clear();
+            state.mode = InputMode::Normal;
+        }
+        KeyCode::Enter => {
+            state.mode = InputMode::Normal;
+        }
+        KeyCode::Backspace => {
+            state.filter.pop();
+        }
+        KeyCode::Down => {
+            let rows = state.visible(mappings);
+            state.select_row(&rows, state.selected_row + 1);
+        }
+        KeyCode::Up => {
+            let rows = state.visible(mappings);
+            state.select_row(&rows, state.selected_row.saturating_sub(1));
+        }
+        KeyCode::Char(c) if c.is_ascii_graphic() || c == ' ' => {
+            state.filter.push(c);
+        }
+        _ => {}
     }
+    InputResult::Continue
 }
 
-/// Process a key event in Adding mode.
-pub fn handle_adding_key(key: KeyEvent, state: &mut TuiState) -> InputResult {
+/// Process a key event in the add/edit popup.
+pub fn handle_popup_key(key: KeyEvent, state: &mut TuiState) -> InputResult {
     match key.code {
         KeyCode::Esc => {
             state.mode = InputMode::Normal;
             InputResult::Continue
         }
-        KeyCode::Tab | KeyCode::BackTab => {
+        KeyCode::Tab => {
             state.popup_field = match state.popup_field {
                 PopupField::Domain => PopupField::Port,
+                PopupField::Port => PopupField::Health,
+                PopupField::Health => PopupField::Tags,
+                PopupField::Tags => PopupField::Domain,
+            };
+            InputResult::Continue
+        }
+        KeyCode::BackTab => {
+            state.popup_field = match state.popup_field {
+                PopupField::Domain => PopupField::Tags,
                 PopupField::Port => PopupField::Domain,
+                PopupField::Health => PopupField::Port,
+                PopupField::Tags => PopupField::Health,
             };
             InputResult::Continue
         }
@@ -65,6 +219,12 @@ pub fn handle_adding_key(key: KeyEvent, state: &mut TuiState) -> InputResult {
                 PopupField::Port => {
                     state.port_input.pop();
                 }
+                PopupField::Health => {
+                    state.health_input.pop();
+                }
+                PopupField::Tags => {
+                    state.tags_input.pop();
+                }
             }
             InputResult::Continue
         }
@@ -82,6 +242,16 @@ pub fn handle_adding_key(key: KeyEvent, state: &mut TuiState) -> InputResult {
                         state.port_input.push(c);
                     }
                 }
+                PopupField::Health => {
+                    if c.is_ascii_graphic() || c == ' ' {
+                        state.health_input.push(c);
+                    }
+                }
+                PopupField::Tags => {
+                    if c.is_alphanumeric() || matches!(c, '-' | '_' | ' ' | ',') {
+                        state.tags_input.push(c);
+                    }
+                }
             }
             InputResult::Continue
         }
@@ -93,21 +263,103 @@ pub fn handle_adding_key(key: KeyEvent, state: &mut TuiState) -> InputResult {
     }
 }
 
+/// Names that can't be mapped: `localhost.localhost` and `www.localhost`
+/// would shadow the bare names in browsers and tooling.
+const RESERVED: &[&str] = &["localhost", "www"];
+
+/// Problems with the popup input so far, for showing as the user types.
+/// Empty fields aren't complained about until the popup is submitted.
+pub fn check_input(state: &TuiState, mappings: &[Mapping], health: &HealthConfig) -> Vec<InputIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |field, severity, message: String| {
+        issues.push(InputIssue {
+            field,
+            severity,
+            message,
+        })
+    };
+
+    let domain_base = state.domain_input.trim().to_lowercase();
+    if !domain_base.is_empty() {
+        let domain = format!("{}.localhost", domain_base);
+        let editing = matches!(&state.mode, InputMode::Editing(original) if *original == domain);
+        if !domain_base
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            issue(
+                PopupField::Domain,
+                Severity::Error,
+                "Domain can only contain letters, numbers, and hyphens".to_string(),
+            );
+        } else if domain_base.starts_with('-') || domain_base.ends_with('-') {
+            issue(
+                PopupField::Domain,
+                Severity::Error,
+                "Domain cannot start or end with a hyphen".to_string(),
+            );
+        } else if RESERVED.contains(&domain_base.as_str()) {
+            issue(PopupField::Domain, Severity::Error, format!("{} is reserved", domain));
+        } else if let Some(existing) = mappings.iter().find(|m| m.domain == domain && !editing) {
+            issue(
+                PopupField::Domain,
+                Severity::Error,
+                format!("{} is already mapped to :{}", domain, existing.port),
+            );
+        }
+    }
+
+    let port_input = state.port_input.trim();
+    if !port_input.is_empty() {
+        match port_input.parse::<u16>() {
+            Ok(0) | Err(_) => issue(
+                PopupField::Port,
+                Severity::Error,
+                "Port must be between 1 and 65535".to_string(),
+            ),
+            Ok(port) if state.session.listen.iter().any(|a| a.port() == port) => issue(
+                PopupField::Port,
+                Severity::Error,
+                format!("portmap itself listens on :{}; this would loop", port),
+            ),
+            // Unusual for a dev server, but nothing stops one running there
+            Ok(port) if port < 1024 => issue(
+                PopupField::Port,
+                Severity::Warning,
+                "Ports below 1024 are usually system services".to_string(),
+            ),
+            Ok(port) => {
+                if matches!(state.port_listening, Some((p, false, _)) if p == port) {
+                    issue(
+                        PopupField::Port,
+                        Severity::Warning,
+                        format!("Nothing is listening on :{} yet", port),
+                    );
+                }
+            }
+        }
+    }
+
+    if let Err(e) = HealthCheck::parse(&state.health_input, health) {
+        issue(PopupField::Health, Severity::Error, e);
+    }
+    issues
+}
+
 /// Validate and create a mapping from the current popup input.
 /// Returns Ok(Mapping) or Err(error message).
-pub fn validate_input(state: &TuiState) -> Result<Mapping, String> {
+pub fn validate_input(
+    state: &TuiState,
+    mappings: &[Mapping],
+    health: &HealthConfig,
+) -> Result<Mapping, String> {
     let domain_base = state.domain_input.trim().to_lowercase();
     if domain_base.is_empty() {
         return Err("Domain cannot be empty".to_string());
     }
-    if !domain_base
-        .chars()
-        .all(|c| c.is_ascii_alphanumeric() || c == '-')
-    {
-        return Err("Domain can only contain letters, numbers, and hyphens".to_string());
-    }
-    if domain_base.starts_with('-') || domain_base.ends_with('-') {
-        return Err("Domain cannot start or end with a hyphen".to_string());
+    let errors = check_input(state, mappings, health);
+    if let Some(error) = errors.into_iter().find(|i| i.severity == Severity::Error) {
+        return Err(error.message);
     }
 
     let port: u16 = state
@@ -116,26 +368,120 @@ pub fn validate_input(state: &TuiState) -> Result<Mapping, String> {
         .parse()
         .map_err(|_| "Port must be a number between 1 and 65535".to_string())?;
 
-    if port == 0 {
-        return Err("Port must be between 1 and 65535".to_string());
-    }
-
     let domain = format!("{}.localhost", domain_base);
 
-    Ok(Mapping {
-        domain,
-        port,
-        status: MappingStatus::Unknown,
-    })
+    let mut mapping = Mapping::new(domain, port);
+    mapping.health = HealthCheck::parse(&state.health_input, health)?;
+    mapping.tags = parse_tags(&state.tags_input);
+    Ok(mapping)
 }
 
-/// Check if a port is reachable by attempting a TCP connection.
-pub async fn check_port(port: u16) -> MappingStatus {
-    if tokio::net::TcpStream::connect(format!("127.0.0.1:{}", port)).await.is_ok() {
-        return MappingStatus::Active;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::SessionInfo;
+    use crate::privilege::RunMode;
+
+    fn popup(domain: &str, port: &str) -> TuiState {
+        let mut state = TuiState::new(SessionInfo {
+            mode: RunMode::Rootless,
+            listen: vec!["127.0.0.1:8080".parse().unwrap()],
+            self_update: true,
+        });
+        state.mode = InputMode::Adding;
+        state.domain_input = domain.to_string();
+        state.port_input = port.to_string();
+        state
+    }
+
+    fn messages(state: &TuiState, mappings: &[Mapping]) -> Vec<(PopupField, Severity, String)> {
+        check_input(state, mappings, &HealthConfig::default())
+            .into_iter()
+            .map(|i| (i.field, i.severity, i.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_input() {
+        let mappings = vec![Mapping::new("api.localhost".to_string(), 3000)];
+        let error = |field, message: &str| vec![(field, Severity::Error, message.to_string())];
+        let warning = |field, message: &str| vec![(field, Severity::Warning, message.to_string())];
+
+        assert!(messages(&popup("", ""), &mappings).is_empty());
+        assert!(messages(&popup("web", "3001"), &mappings).is_empty());
+        assert_eq!(
+            messages(&popup("API", "3001"), &mappings),
+            error(PopupField::Domain, "api.localhost is already mapped to :3000")
+        );
+        assert_eq!(
+            messages(&popup("www", ""), &mappings),
+            error(PopupField::Domain, "www.localhost is reserved")
+        );
+        assert_eq!(
+            messages(&popup("", "443"), &mappings),
+            warning(PopupField::Port, "Ports below 1024 are usually system services")
+        );
+        assert_eq!(
+            messages(&popup("", "8080"), &mappings),
+            error(PopupField::Port, "portmap itself listens on :8080; this would loop")
+        );
+        assert_eq!(
+            messages(&popup("", "70000"), &mappings),
+            error(PopupField::Port, "Port must be between 1 and 65535")
+        );
+
+        // Editing a mapping doesn't clash with itself
+        let mut state = popup("api", "3000");
+        state.mode = InputMode::Editing("api.localhost".to_string());
+        assert!(messages(&state, &mappings).is_empty());
+
+        state.health_input = "healthz".to_string();
+        assert_eq!(messages(&state, &mappings)[0].0, PopupField::Health);
     }
-    if tokio::net::TcpStream::connect(format!("[::1]:{}", port)).await.is_ok() {
-        return MappingStatus::Active;
+
+    #[test]
+    fn test_nothing_listening_is_only_a_warning() {
+        let mut state = popup("web", "3001");
+        state.port_listening = Some((3001, false, Instant::now()));
+        assert_eq!(
+            messages(&state, &[]),
+            vec![(
+                PopupField::Port,
+                Severity::Warning,
+                "Nothing is listening on :3001 yet".to_string()
+            )]
+        );
+        assert!(validate_input(&state, &[], &HealthConfig::default()).is_ok());
+
+        // A result for another port doesn't apply
+        state.port_input = "3002".to_string();
+        assert!(messages(&state, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_validate_input_rejects_errors() {
+        let mappings = vec![Mapping::new("api.localhost".to_string(), 3000)];
+        let health = HealthConfig::default();
+        assert_eq!(
+            validate_input(&popup("", "3000"), &mappings, &health).unwrap_err(),
+            "Domain cannot be empty"
+        );
+        assert_eq!(
+            validate_input(&popup("web", ""), &mappings, &health).unwrap_err(),
+            "Port must be a number between 1 and 65535"
+        );
+        assert_eq!(
+            validate_input(&popup("api", "3001"), &mappings, &health).unwrap_err(),
+            "api.localhost is already mapped to :3000"
+        );
+        let mapping = validate_input(&popup("Web", "3001"), &mappings, &health).unwrap();
+        assert_eq!((mapping.domain.as_str(), mapping.port), ("web.localhost", 3001));
+        let mut state = popup("web", "3001");
+        state.tags_input = "frontend,vite".to_string();
+        let mapping = validate_input(&state, &mappings, &health).unwrap();
+        assert_eq!(mapping.tags, ["frontend", "vite"]);
+        // Low ports only warn
+        let mapping = validate_input(&popup("legacy", "81"), &mappings, &health).unwrap();
+        assert_eq!(mapping.port, 81);
     }
-    MappingStatus::PortUnreachable
 }
@@ -0,0 +1,419 @@
+//! Keybindings for Normal mode, and the help overlay listing them.
+//!
+//! Every action has default keys, which `[tui.keys]` in the config file
+//! can replace per action. The popup and filter keys are fixed.
+
+use crate::app::InputMode;
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Something a key can do in Normal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Add,
+    Edit,
+    Open,
+    Copy,
+    Toggle,
+    Delete,
+    Undo,
+    Redo,
+    Filter,
+    Sort,
+    Down,
+    Up,
+    Update,
+    Help,
+    Quit,
+}
+
+impl Action {
+    /// All actions, in the order the help overlay lists them.
+    pub const ALL: [Action; 15] = [
+        Action::Add,
+        Action::Edit,
+        Action::Open,
+        Action::Copy,
+        Action::Toggle,
+        Action::Delete,
+        Action::Undo,
+        Action::Redo,
+        Action::Filter,
+        Action::Sort,
+        Action::Down,
+        Action::Up,
+        Action::Update,
+        Action::Help,
+        Action::Quit,
+    ];
+
+    /// Name used in `[tui.keys]`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Add => "add",
+            Action::Edit => "edit",
+            Action::Open => "open",
+            Action::Copy => "copy",
+            Action::Toggle => "toggle",
+            Action::Delete => "delete",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Filter => "filter",
+            Action::Sort => "sort",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::Update => "update",
+            Action::Help => "help",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Add => "Add a new mapping",
+            Action::Edit => "Edit selected mapping",
+            Action::Open => "Open selected mapping in the browser",
+            Action::Copy => "Copy selected mapping's URL",
+            Action::Toggle => "Disable or re-enable selected mapping",
+            Action::Delete => "Delete selected mapping",
+            Action::Undo => "Undo the last change",
+            Action::Redo => "Redo",
+            Action::Filter => "Filter the table",
+            Action::Sort => "Cycle sort order",
+            Action::Down => "Move selection down",
+            Action::Up => "Move selection up",
+            Action::Update => "Install an available update",
+            Action::Help => "Show this help",
+            Action::Quit => "Quit and clean up",
+        }
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Add => &["a"],
+            Action::Edit => &["e", "enter"],
+            Action::Open => &["o"],
+            Action::Copy => &["y"],
+            Action::Toggle => &["space"],
+            Action::Delete => &["d"],
+            Action::Undo => &["u"],
+            Action::Redo => &["ctrl-r"],
+            Action::Filter => &["/"],
+            Action::Sort => &["s"],
+            Action::Down => &["j", "down"],
+            Action::Up => &["k", "up"],
+            Action::Update => &["U"],
+            Action::Help => &["?", "f1"],
+            Action::Quit => &["q"],
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+/// A key with its Ctrl/Alt modifiers, as written in the config file:
+/// `a`, `U`, `ctrl-r`, `alt-enter`, `space`, `f1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("space", KeyCode::Char(' ')),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+impl KeyBinding {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        // `-` on its own (or after a modifier) is the minus key
+        while let Some((prefix, key)) = rest.split_once('-').filter(|(_, k)| !k.is_empty()) {
+            modifiers |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+            rest = key;
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let lower = rest.to_ascii_lowercase();
+                match NAMED_KEYS.iter().find(|(name, _)| *name == lower) {
+                    Some((_, code)) => *code,
+                    None => {
+                        let n = lower.strip_prefix('f')?.parse().ok()?;
+                        if !(1..=12).contains(&n) {
+                            return None;
+                        }
+                        KeyCode::F(n)
+                    }
+                }
+            }
+        };
+        Some(Self::new(code, modifiers))
+    }
+
+    /// Shift is part of the character for printable keys (`U`, `?`).
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if matches!(code, KeyCode::Char(_)) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+
+    /// The key event this binding matches, for synthesizing key presses.
+    pub fn event(self) -> KeyEvent {
+        KeyEvent::new(self.code, self.modifiers)
+    }
+}
+
+impl From<KeyEvent> for KeyBinding {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl-"),
+            (KeyModifiers::ALT, "alt-"),
+            (KeyModifiers::SHIFT, "shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, code)| *code == self.code) {
+            return f.write_str(name);
+        }
+        match self.code {
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// Which keys do what in Normal mode.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(KeyBinding, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_config(&BTreeMap::new()).expect("default keymap is valid")
+    }
+}
+
+impl Keymap {
+    /// The default keys, with the actions in `keys` rebound. Fails on
+    /// unknown actions, unparseable keys, a key bound to two actions, or
+    /// no way to quit.
+    pub fn from_config(keys: &BTreeMap<String, Vec<String>>) -> Result<Self> {
+        for name in keys.keys() {
+            if Action::from_name(name).is_none() {
+                let names: Vec<_> = Action::ALL.iter().map(|a| a.name()).collect();
+                bail!(
+                    "Unknown action `{}` in [tui.keys]; expected one of {}",
+                    name,
+                    names.join(", ")
+                );
+            }
+        }
+
+        let mut bindings: Vec<(KeyBinding, Action)> = Vec::new();
+        for action in Action::ALL {
+            let configured = keys.get(action.name());
+            let specs: Vec<&str> = match configured {
+                Some(specs) => specs.iter().map(String::as_str).collect(),
+                None => action.default_keys().to_vec(),
+            };
+            for spec in specs {
+                let Some(key) = KeyBinding::parse(spec) else {
+                    bail!("Invalid key `{}` for `{}` in [tui.keys]", spec, action.name());
+                };
+                match bindings.iter().find(|(k, _)| *k == key) {
+                    Some((_, other)) if *other == action => {}
+                    Some((_, other)) => bail!(
+                        "`{}` is bound to both `{}` and `{}`; rebind one of them in [tui.keys]",
+                        key,
+                        other.name(),
+                        action.name()
+                    ),
+                    None => bindings.push((key, action)),
+                }
+            }
+        }
+
+        if !bindings.iter().any(|(_, a)| *a == Action::Quit) {
+            bail!("`quit` needs at least one key in [tui.keys]");
+        }
+        Ok(Self { bindings })
+    }
+
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        let key = KeyBinding::from(key);
+        self.bindings.iter().find(|(k, _)| *k == key).map(|(_, a)| *a)
+    }
+
+    /// Keys bound to `action`, in the order they were configured.
+    pub fn keys(&self, action: Action) -> Vec<KeyBinding> {
+        self.bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    /// The first key bound to `action`, as shown in hints.
+    pub fn key(&self, action: Action) -> Option<KeyBinding> {
+        self.keys(action).into_iter().next()
+    }
+
+    /// Every action available in `mode`, as (keys, description) pairs.
+    pub fn help(&self, mode: &InputMode) -> Vec<(String, &'static str)> {
+        let fixed: &[(&str, &str)] = match mode {
+            InputMode::Normal => {
+                return Action::ALL
+                    .into_iter()
+                    .filter_map(|action| {
+                        let keys = self.keys(action);
+                        if keys.is_empty() {
+                            return None;
+                        }
+                        let keys: Vec<_> = keys.iter().map(|k| k.to_string()).collect();
+                        Some((keys.join(", "), action.description()))
+                    })
+                    .collect();
+            }
+            InputMode::Adding | InputMode::Editing(_) => &[
+                ("tab", "Next field"),
+                ("shift-tab", "Previous field"),
+                ("enter", "Save the mapping"),
+                ("esc", "Cancel"),
+                ("f1", "Show this help"),
+            ],
+            InputMode::Filtering => &[
+                ("(type)", "Narrow the table"),
+                ("backspace", "Delete a character"),
+                ("up, down", "Move selection"),
+                ("enter", "Keep the filter"),
+                ("esc", "Clear the filter"),
+                ("f1", "Show this help"),
+            ],
+            InputMode::ConfirmDelete(_) => &[
+                ("y", "Delete the mapping"),
+                ("(any other)", "Keep it"),
+                ("f1", "Show this help"),
+            ],
+        };
+        fixed.iter().map(|(k, d)| (k.to_string(), *d)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(action, keys)| {
+                (action.to_string(), keys.iter().map(|k| k.to_string()).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let key = |s| KeyBinding::parse(s).unwrap();
+        assert_eq!(key("a").code, KeyCode::Char('a'));
+        assert_eq!(key("U").code, KeyCode::Char('U'));
+        assert_eq!(key("space").code, KeyCode::Char(' '));
+        assert_eq!(key("F5").code, KeyCode::F(5));
+        assert_eq!(key("-").code, KeyCode::Char('-'));
+        assert_eq!(key("ctrl--").modifiers, KeyModifiers::CONTROL);
+        let ctrl_r = key("Ctrl-r");
+        assert_eq!((ctrl_r.code, ctrl_r.modifiers), (KeyCode::Char('r'), KeyModifiers::CONTROL));
+        assert!(KeyBinding::parse("").is_none());
+        assert!(KeyBinding::parse("hyper-a").is_none());
+        assert!(KeyBinding::parse("f13").is_none());
+        assert!(KeyBinding::parse("enterr").is_none());
+
+        for s in ["a", "U", "ctrl-r", "alt-enter", "space", "f1", "shift-up"] {
+            assert_eq!(key(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_default_keymap() {
+        let keymap = Keymap::default();
+        let press = |code, modifiers| keymap.action(KeyEvent::new(code, modifiers));
+        assert_eq!(press(KeyCode::Char('a'), KeyModifiers::NONE), Some(Action::Add));
+        // Terminals report uppercase letters with Shift held
+        assert_eq!(press(KeyCode::Char('U'), KeyModifiers::SHIFT), Some(Action::Update));
+        assert_eq!(press(KeyCode::Char('r'), KeyModifiers::CONTROL), Some(Action::Redo));
+        assert_eq!(press(KeyCode::Char('r'), KeyModifiers::NONE), None);
+        assert_eq!(press(KeyCode::Enter, KeyModifiers::NONE), Some(Action::Edit));
+        assert_eq!(keymap.help(&InputMode::Normal).len(), Action::ALL.len());
+    }
+
+    #[test]
+    fn test_rebinding() {
+        let keymap = Keymap::from_config(&keys(&[("delete", &["x", "delete"]), ("help", &[])]))
+            .unwrap();
+        assert_eq!(keymap.action(KeyEvent::from(KeyCode::Char('x'))), Some(Action::Delete));
+        assert_eq!(keymap.action(KeyEvent::from(KeyCode::Delete)), Some(Action::Delete));
+        // Rebinding replaces the defaults
+        assert_eq!(keymap.action(KeyEvent::from(KeyCode::Char('d'))), None);
+        assert_eq!(keymap.key(Action::Help), None);
+        assert!(keymap.help(&InputMode::Normal).iter().all(|(_, d)| *d != "Show this help"));
+    }
+
+    #[test]
+    fn test_invalid_keymaps() {
+        let err = |entries: &[(&str, &[&str])]| {
+            Keymap::from_config(&keys(entries)).unwrap_err().to_string()
+        };
+        assert_eq!(
+            err(&[("add", &["d"])]),
+            "`d` is bound to both `add` and `delete`; rebind one of them in [tui.keys]"
+        );
+        assert_eq!(
+            err(&[("copy", &["ctrl-r"])]),
+            "`ctrl-r` is bound to both `copy` and `redo`; rebind one of them in [tui.keys]"
+        );
+        assert!(err(&[("frobnicate", &["f"])]).starts_with("Unknown action `frobnicate`"));
+        assert_eq!(err(&[("add", &["ctrl+a"])]), "Invalid key `ctrl+a` for `add` in [tui.keys]");
+        assert_eq!(err(&[("quit", &[])]), "`quit` needs at least one key in [tui.keys]");
+        // Swapping two keys is fine
+        assert!(Keymap::from_config(&keys(&[("add", &["d"]), ("delete", &["a"])])).is_ok());
+    }
+}
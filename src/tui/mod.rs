@@ -1,3 +1,7 @@
+pub mod browser;
+pub mod history;
 pub mod input;
+pub mod keymap;
 pub mod terminal;
+pub mod theme;
 pub mod ui;
@@ -1,11 +1,25 @@
-use crate::app::{InputMode, Mapping, TuiState};
-use crate::hosts::manager::HostsManager;
+use crate::app::{InputMode, Mapping, SessionInfo, TuiState};
+use crate::config::Config;
+use crate::events::StatusEvent;
+use crate::hosts::backend::HostsBackend;
+use crate::hosts::watcher::HostsDrift;
+use crate::probe::{is_listening, ProbeReport};
+use crate::proxy::metrics::ProxyMetrics;
+use crate::proxy::server::{ProxyPhase, ProxyStatus};
+use crate::tui::browser::{copy_to_clipboard, mapping_url, open_url};
+use crate::tui::history::{Change, History, Target};
 use crate::tui::input::{
-    check_port, handle_adding_key, handle_normal_key, validate_input, InputResult,
+    check_input, handle_action, handle_filter_key, handle_mouse, handle_popup_key,
+    validate_input, InputResult, MouseAction,
 };
-use crate::tui::ui;
+use crate::tui::keymap::{Action, Keymap};
+use crate::tui::theme::Theme;
+use crate::tui::ui::{self, ClickMap};
+use crate::update::{self, release::Release};
 use anyhow::Result;
-use crossterm::event::{Event, EventStream, KeyCode, KeyEventKind};
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEventKind,
+};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
@@ -14,30 +28,100 @@ use futures::StreamExt;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::io;
-use std::time::Duration;
-use tokio::sync::watch;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+
+/// How long a "nothing listening" result for the popup's port holds.
+const PORT_RECHECK: Duration = Duration::from_secs(2);
+
+/// Channels connecting the TUI to the rest of the process.
+pub struct TuiChannels {
+    /// Mapping list shared with the proxy, DNS responder and hosts watcher
+    pub mappings: watch::Sender<Vec<Mapping>>,
+    pub probes: mpsc::Receiver<ProbeReport>,
+    /// Status changes, for notifications and hooks
+    pub events: mpsc::UnboundedSender<StatusEvent>,
+    pub drift: watch::Receiver<Option<HostsDrift>>,
+    pub update: watch::Receiver<Option<Release>>,
+    pub proxy: watch::Receiver<ProxyStatus>,
+    pub shutdown: watch::Sender<bool>,
+    /// Request counters kept by the proxy
+    pub metrics: Arc<ProxyMetrics>,
+}
 
 /// Run the TUI event loop.
+///
+/// Quitting (or a shutdown signal) tells the proxy to stop, then keeps
+/// the UI up until it has drained its connections.
 pub async fn run_tui(
-    mappings_tx: watch::Sender<Vec<Mapping>>,
-    hosts_manager: HostsManager,
-    mut shutdown_rx: watch::Receiver<bool>,
+    channels: TuiChannels,
+    hosts: Arc<dyn HostsBackend>,
+    config: Config,
+    keymap: Keymap,
+    theme: Theme,
+    session: SessionInfo,
 ) -> Result<()> {
+    let TuiChannels {
+        mappings: mappings_tx,
+        probes: mut probe_rx,
+        events: event_tx,
+        drift: mut drift_rx,
+        update: mut update_rx,
+        proxy: mut proxy_rx,
+        shutdown: shutdown_tx,
+        metrics,
+    } = channels;
+    let mut shutdown_rx = shutdown_tx.subscribe();
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut state = TuiState::new();
+    let mut state = TuiState::new(session);
+    let mut history = History::default();
+    let mut clicks = ClickMap::default();
+    let target = Target {
+        mappings: &mappings_tx,
+        hosts: hosts.as_ref(),
+        comment_out_conflicts: config.hosts.comment_out_conflicts,
+    };
     let mut reader = EventStream::new();
-    let mut status_check_interval = tokio::time::interval(Duration::from_secs(3));
+    // Keeps uptimes and the request rate in the detail pane moving
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let (install_tx, mut install_rx) = mpsc::unbounded_channel::<(Release, Result<()>)>();
+    let mut installing = false;
+    let (port_tx, mut port_rx) = mpsc::unbounded_channel::<(u16, bool)>();
+    let mut probing: Option<u16> = None;
 
     let result = loop {
+        state.proxy = *proxy_rx.borrow();
+        // A closed channel means the proxy task is gone entirely
+        let proxy_stopped =
+            state.proxy.phase == ProxyPhase::Stopped || proxy_rx.has_changed().is_err();
+        if state.draining && proxy_stopped {
+            break Ok(());
+        }
+
         // Draw
         let mappings = mappings_tx.borrow().clone();
-        terminal.draw(|f| ui::draw(f, &state, &mappings))?;
+        state.sync_selection(&state.visible(&mappings));
+        if matches!(state.mode, InputMode::Adding | InputMode::Editing(_)) {
+            if let Some(port) = port_to_probe(&state).filter(|port| probing != Some(*port)) {
+                // The result comes back below; the popup doesn't wait for it
+                probing = Some(port);
+                let port_tx = port_tx.clone();
+                tokio::spawn(async move {
+                    let listening = is_listening(port, Duration::from_millis(100)).await;
+                    let _ = port_tx.send((port, listening));
+                });
+            }
+            state.input_issues = check_input(&state, &mappings, &config.health);
+        }
+        terminal.draw(|f| clicks = ui::draw(f, &state, &mappings, &keymap, &theme, &metrics))?;
+        state.table_offset = clicks.offset;
 
         tokio::select! {
             // Terminal events
@@ -45,99 +129,272 @@ pub async fn run_tui(
                 let Some(Ok(event)) = maybe_event else {
                     break Ok(());
                 };
-                if let Event::Key(key) = event {
+                let key = match event {
                     // crossterm sends both Press and Release on some platforms
-                    if key.kind != KeyEventKind::Press {
-                        continue;
+                    Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                    // Row clicks act directly; the wheel and footer hints stand in for keys
+                    Event::Mouse(mouse) if state.mode == InputMode::Normal && !state.draining => {
+                        match handle_mouse(mouse, &mut state, &mappings, &clicks) {
+                            MouseAction::Key(key) => key,
+                            MouseAction::Open(domain) => {
+                                open_mapping(&mut state, &domain);
+                                continue;
+                            }
+                            MouseAction::None => continue,
+                        }
                     }
+                    _ => continue,
+                };
+                let action = keymap.action(key);
+                // Only a second quit does anything while draining
+                if state.draining {
+                    if action == Some(Action::Quit) {
+                        break Ok(());
+                    }
+                    continue;
+                }
+                // Any key closes the help overlay; F1 opens it outside Normal mode
+                if state.show_help {
+                    state.show_help = false;
+                    continue;
+                }
+                if key.code == KeyCode::F(1) && state.mode != InputMode::Normal {
+                    state.show_help = true;
+                    continue;
+                }
 
-                    match state.mode {
-                        InputMode::Normal => {
-                            // Handle delete specially since it needs mutable mappings
-                            if key.code == KeyCode::Char('d') {
-                                let mut mappings = mappings_tx.borrow().clone();
-                                if !mappings.is_empty() && state.selected < mappings.len() {
-                                    let removed = mappings.remove(state.selected);
-                                    let _ = hosts_manager.remove_entry(&removed.domain);
-                                    if state.selected > 0 && state.selected >= mappings.len() {
-                                        state.selected = mappings.len().saturating_sub(1);
-                                    }
-                                    state.status_message = Some(format!("Removed {}", removed.domain));
-                                    mappings_tx.send(mappings)?;
+                match state.mode {
+                    InputMode::Normal => {
+                        let Some(action) = action else {
+                            continue;
+                        };
+                        // Changes to the mapping list are handled here, recorded for undo
+                        let selected = state
+                            .selected_mapping(&mappings)
+                            .map(|(index, m)| (index, m.clone()));
+                        let change = match (action, selected) {
+                            (Action::Delete, Some((index, mapping))) => {
+                                if config.tui.confirm_delete {
+                                    state.status_message =
+                                        Some(format!("Delete {}? (y/n)", mapping.domain));
+                                    state.mode = InputMode::ConfirmDelete(mapping.domain);
+                                    continue;
                                 }
-                                continue;
+                                Some(Change::Remove(index, mapping))
+                            }
+                            (Action::Toggle, Some((_, mapping))) => {
+                                let mut toggled = mapping.clone();
+                                toggled.enabled = !mapping.enabled;
+                                Some(Change::Replace {
+                                    before: mapping,
+                                    after: toggled,
+                                })
                             }
+                            _ => None,
+                        };
+                        if let Some(change) = change {
+                            state.status_message = Some(
+                                history.perform(change, &target).unwrap_or_else(|e| e),
+                            );
+                            continue;
+                        }
 
-                            match handle_normal_key(key, &mut state, &mappings_tx.borrow()) {
-                                InputResult::Quit => break Ok(()),
-                                InputResult::Continue => {}
+                        if matches!(action, Action::Undo | Action::Redo) {
+                            let result = if action == Action::Undo {
+                                history.undo(&target)
+                            } else {
+                                history.redo(&target)
+                            };
+                            state.status_message = Some(result.unwrap_or_else(|e| e));
+                            continue;
+                        }
+
+                        if action == Action::Update {
+                            if state.update.is_some() && !state.session.self_update {
+                                state.status_message = update::self_update_problem();
+                            } else if let (Some(release), false) = (state.update.clone(), installing) {
+                                state.status_message =
+                                    Some(format!("Downloading {}\u{2026}", release.tag_name));
+                                installing = true;
+                                // Runs off the loop so the UI stays live; the result comes back below
+                                let update_config = config.update.clone();
+                                let install_tx = install_tx.clone();
+                                tokio::task::spawn_blocking(move || {
+                                    let result = update::install(&update_config, &release);
+                                    let _ = install_tx.send((release, result));
+                                });
                             }
+                            continue;
                         }
-                        InputMode::Adding => {
-                            if key.code == KeyCode::Enter {
-                                match validate_input(&state) {
-                                    Ok(mut mapping) => {
-                                        // Check port reachability
-                                        mapping.status = check_port(mapping.port).await;
 
-                                        // Add to hosts file
-                                        match hosts_manager.add_entry(&mapping.domain) {
-                                            Ok(true) => {
-                                                let mut mappings = mappings_tx.borrow().clone();
-                                                state.status_message = Some(format!(
-                                                    "Added {} \u{2192} :{}",
-                                                    mapping.domain, mapping.port
-                                                ));
-                                                mappings.push(mapping);
-                                                mappings_tx.send(mappings)?;
-                                                state.mode = InputMode::Normal;
-                                            }
-                                            Ok(false) => {
-                                                state.status_message = Some("Mapping already exists".to_string());
-                                            }
-                                            Err(e) => {
-                                                state.status_message = Some(format!("Error: {}", e));
-                                            }
+                        match handle_action(action, &mut state, &mappings_tx.borrow()) {
+                            InputResult::Quit => {
+                                shutdown_tx.send(true)?;
+                            }
+                            InputResult::Open(domain) => open_mapping(&mut state, &domain),
+                            InputResult::Copy(domain) => {
+                                let url = mapping_url(&domain, &state.session.listen);
+                                state.status_message = Some(match copy_to_clipboard(&url) {
+                                    Ok(()) => format!("Copied {}", url),
+                                    Err(e) => format!("Error: {:#}", e),
+                                });
+                            }
+                            InputResult::Continue => {}
+                        }
+                    }
+                    InputMode::ConfirmDelete(ref domain) => {
+                        let mapping = mappings_tx
+                            .borrow()
+                            .iter()
+                            .enumerate()
+                            .find(|(_, m)| &m.domain == domain)
+                            .map(|(i, m)| (i, m.clone()));
+                        state.status_message = match (key.code, mapping) {
+                            (KeyCode::Char('y'), Some((index, mapping))) => Some(
+                                history
+                                    .perform(Change::Remove(index, mapping), &target)
+                                    .unwrap_or_else(|e| e),
+                            ),
+                            _ => None,
+                        };
+                        state.mode = InputMode::Normal;
+                    }
+                    InputMode::Filtering => {
+                        handle_filter_key(key, &mut state, &mappings);
+                    }
+                    InputMode::Adding | InputMode::Editing(_) => {
+                        if key.code == KeyCode::Enter {
+                            let result = validate_input(&state, &mappings, &config.health).and_then(|mapping| {
+                                let domain = mapping.domain.clone();
+                                let change = match &state.mode {
+                                    InputMode::Editing(original) => {
+                                        let mappings = mappings_tx.borrow();
+                                        let Some(before) =
+                                            mappings.iter().find(|m| &m.domain == original)
+                                        else {
+                                            return Err(format!("{} no longer exists", original));
+                                        };
+                                        let mut after = mapping;
+                                        after.enabled = before.enabled;
+                                        Change::Replace {
+                                            before: before.clone(),
+                                            after,
                                         }
                                     }
-                                    Err(msg) => {
-                                        state.status_message = Some(msg);
-                                    }
+                                    _ => Change::Insert(mappings_tx.borrow().len(), mapping),
+                                };
+                                history.perform(change, &target).map(|message| (domain, message))
+                            });
+                            match result {
+                                Ok((domain, message)) => {
+                                    // Follow the new or renamed mapping
+                                    state.selected = Some(domain);
+                                    state.status_message = Some(message);
+                                    state.mode = InputMode::Normal;
+                                }
+                                Err(msg) => {
+                                    state.status_message = Some(msg);
                                 }
-                                continue;
                             }
-                            handle_adding_key(key, &mut state);
+                            continue;
                         }
+                        handle_popup_key(key, &mut state);
                     }
                 }
             }
-            // Periodic port status checks
-            _ = status_check_interval.tick() => {
-                let mut mappings = mappings_tx.borrow().clone();
-                let mut changed = false;
-                for mapping in &mut mappings {
-                    let new_status = check_port(mapping.port).await;
-                    if new_status != mapping.status {
-                        mapping.status = new_status;
-                        changed = true;
+            // Status probes from the background prober
+            Some(report) = probe_rx.recv() => {
+                mappings_tx.send_if_modified(|mappings| {
+                    // The mapping may have been removed or changed meanwhile
+                    let Some(mapping) = mappings
+                        .iter_mut()
+                        .find(|m| m.domain == report.domain && m.port == report.port)
+                    else {
+                        return false;
+                    };
+                    if mapping.status == report.probe.status && mapping.latency == report.probe.latency {
+                        return false;
                     }
+                    if mapping.status != report.probe.status {
+                        state.status_changed.insert(mapping.domain.clone(), Instant::now());
+                    }
+                    if let Some(event) = StatusEvent::transition(
+                        &mapping.domain,
+                        mapping.port,
+                        &mapping.status,
+                        &report.probe.status,
+                    ) {
+                        let _ = event_tx.send(event);
+                    }
+                    mapping.status = report.probe.status;
+                    mapping.latency = report.probe.latency;
+                    true
+                });
+            }
+            // External edits to the hosts file
+            Ok(()) = drift_rx.changed() => {
+                state.hosts_drift = drift_rx.borrow().clone();
+            }
+            // Background update check
+            Ok(()) = update_rx.changed() => {
+                state.update = update_rx.borrow().clone();
+            }
+            // Whether anything listens on the popup's port
+            Some((port, listening)) = port_rx.recv() => {
+                if probing == Some(port) {
+                    probing = None;
                 }
-                if changed {
-                    mappings_tx.send(mappings)?;
-                }
+                state.port_listening = Some((port, listening, Instant::now()));
+            }
+            // An update started with `U` finished
+            Some((release, result)) = install_rx.recv() => {
+                installing = false;
+                state.status_message = Some(match result {
+                    Ok(()) => {
+                        state.update = None;
+                        format!("Updated to {}; restart portmap to use it", release.tag_name)
+                    }
+                    Err(e) => format!("Update failed: {:#}", e),
+                });
             }
-            // Shutdown signal
-            _ = shutdown_rx.changed() => {
+            // Connection count and drain progress
+            Ok(()) = proxy_rx.changed() => {}
+            _ = tick.tick() => {}
+            // Shutdown requested (by quitting or a signal)
+            Ok(()) = shutdown_rx.changed() => {
                 if *shutdown_rx.borrow() {
-                    break Ok(());
+                    state.draining = true;
                 }
             }
         }
     };
 
-    // Restore terminal
+    restore_terminal()?;
+    result
+}
+
+/// Leave raw mode and the alternate screen. Also used when a second signal
+/// exits the process without waiting for the TUI.
+pub fn restore_terminal() -> io::Result<()> {
     disable_raw_mode()?;
-    execute!(io::stdout(), LeaveAlternateScreen)?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)
+}
 
-    result
+fn open_mapping(state: &mut TuiState, domain: &str) {
+    let url = mapping_url(domain, &state.session.listen);
+    state.status_message = Some(match open_url(&url) {
+        Ok(()) => format!("Opened {}", url),
+        Err(e) => format!("Error: {:#}", e),
+    });
+}
+
+/// The port typed in the popup, if whether anything listens on it is
+/// unknown or due a recheck (every couple of seconds, in case the server is
+/// being started).
+fn port_to_probe(state: &TuiState) -> Option<u16> {
+    let port = state.port_input.trim().parse::<u16>().ok()?;
+    state
+        .port_listening
+        .is_none_or(|(p, _, at)| p != port || at.elapsed() > PORT_RECHECK)
+        .then_some(port)
 }
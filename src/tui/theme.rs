@@ -0,0 +1,319 @@
+//! Colours and text styles for the TUI.
+//!
+//! The UI draws with a handful of semantic styles (an ok status, muted
+//! text, ...) rather than colours, so a theme only has to say what each of
+//! those looks like. Themes are picked with `[tui] theme`, and user themes
+//! in `[tui.themes.<name>]` start from a built-in one.
+
+use crate::app::MappingStatus;
+use crate::config::{ThemeConfig, TuiConfig};
+use anyhow::{anyhow, bail, Context, Result};
+use ratatui::style::{Color, Modifier, Style};
+
+/// Built-in theme names, the first being the default.
+pub const BUILTIN: [&str; 4] = ["dark", "light", "high-contrast", "monochrome"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// Title, popup borders, focused labels and most key hints
+    pub accent: Style,
+    /// Table header and the keys in the help overlay
+    pub heading: Style,
+    /// What's being typed
+    pub text: Style,
+    /// Unfocused labels, placeholders and disabled mappings
+    pub muted: Style,
+    /// Working mappings and the running proxy
+    pub ok: Style,
+    /// Starting or timed out mappings, draining, hosts drift
+    pub warn: Style,
+    /// Failing mappings and the delete hint
+    pub error: Style,
+    /// Available updates
+    pub notice: Style,
+    /// The selected row
+    pub selected: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            accent: Style::default().fg(Color::Cyan),
+            heading: Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            text: Style::default().fg(Color::White),
+            muted: Style::default().fg(Color::DarkGray),
+            ok: Style::default().fg(Color::Green),
+            warn: Style::default().fg(Color::Yellow),
+            error: Style::default().fg(Color::Red),
+            notice: Style::default().fg(Color::Magenta),
+            selected: Style::default().add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// For light terminal backgrounds, where yellow and white wash out.
+    pub fn light() -> Self {
+        Self {
+            accent: Style::default().fg(Color::Blue),
+            heading: Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            text: Style::default().fg(Color::Black),
+            muted: Style::default().fg(Color::DarkGray),
+            ok: Style::default().fg(Color::Green),
+            warn: Style::default().fg(Color::Indexed(130)),
+            error: Style::default().fg(Color::Red),
+            notice: Style::default().fg(Color::Magenta),
+            selected: Style::default().add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// Bright text, and blue/orange instead of green/red, which stay apart
+    /// for red-green colour blindness.
+    pub fn high_contrast() -> Self {
+        Self {
+            accent: Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            heading: Style::default()
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            text: Style::default().fg(Color::White),
+            muted: Style::default().fg(Color::Gray),
+            ok: Style::default().fg(Color::Rgb(86, 180, 233)).add_modifier(Modifier::BOLD),
+            warn: Style::default().fg(Color::Rgb(240, 228, 66)),
+            error: Style::default().fg(Color::Rgb(230, 159, 0)).add_modifier(Modifier::BOLD),
+            notice: Style::default().fg(Color::Rgb(204, 121, 167)),
+            selected: Style::default().add_modifier(Modifier::REVERSED),
+        }
+    }
+
+    /// No colours at all, only bold, dim, underline and reverse.
+    pub fn monochrome() -> Self {
+        Self {
+            accent: Style::default().add_modifier(Modifier::BOLD),
+            heading: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            text: Style::default(),
+            muted: Style::default().add_modifier(Modifier::DIM),
+            ok: Style::default(),
+            warn: Style::default().add_modifier(Modifier::BOLD),
+            error: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            notice: Style::default().add_modifier(Modifier::BOLD),
+            selected: Style::default().add_modifier(Modifier::REVERSED),
+        }
+    }
+
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// The theme named by `[tui] theme`. With `no_color` (`$NO_COLOR` is
+    /// set) it's always monochrome. Fails on unknown names and bad styles.
+    pub fn from_config(config: &TuiConfig, no_color: bool) -> Result<Self> {
+        // Check user themes even when another is in use, so typos surface
+        for (name, theme) in &config.themes {
+            Self::from_theme_config(theme)
+                .with_context(|| format!("Invalid theme [tui.themes.{}]", name))?;
+        }
+        if no_color {
+            return Ok(Self::monochrome());
+        }
+        let name = config.theme.as_deref().unwrap_or(BUILTIN[0]);
+        if let Some(theme) = config.themes.get(name) {
+            return Self::from_theme_config(theme);
+        }
+        Self::builtin(name).ok_or_else(|| {
+            let mut names: Vec<&str> = BUILTIN.to_vec();
+            names.extend(config.themes.keys().map(String::as_str));
+            anyhow!("Unknown theme `{}`; expected one of {}", name, names.join(", "))
+        })
+    }
+
+    fn from_theme_config(config: &ThemeConfig) -> Result<Self> {
+        let base = config.base.as_deref().unwrap_or(BUILTIN[0]);
+        let mut theme = Self::builtin(base).ok_or_else(|| {
+            anyhow!("Unknown base theme `{}`; expected one of {}", base, BUILTIN.join(", "))
+        })?;
+        for (slot, spec, name) in [
+            (&mut theme.accent, &config.accent, "accent"),
+            (&mut theme.heading, &config.heading, "heading"),
+            (&mut theme.text, &config.text, "text"),
+            (&mut theme.muted, &config.muted, "muted"),
+            (&mut theme.ok, &config.ok, "ok"),
+            (&mut theme.warn, &config.warn, "warn"),
+            (&mut theme.error, &config.error, "error"),
+            (&mut theme.notice, &config.notice, "notice"),
+            (&mut theme.selected, &config.selected, "selected"),
+        ] {
+            if let Some(spec) = spec {
+                *slot = parse_style(spec).with_context(|| format!("Invalid style for `{}`", name))?;
+            }
+        }
+        Ok(theme)
+    }
+
+    /// Style for a mapping's status.
+    pub fn status(&self, status: &MappingStatus) -> Style {
+        match status {
+            MappingStatus::Active | MappingStatus::Healthy => self.ok,
+            MappingStatus::PortUnreachable | MappingStatus::Unhealthy(_) => self.error,
+            MappingStatus::Starting | MappingStatus::Timeout => self.warn,
+            MappingStatus::Unknown => self.muted,
+        }
+    }
+}
+
+/// A different symbol for each status, so it reads without colour.
+pub fn status_glyph(status: &MappingStatus) -> char {
+    match status {
+        MappingStatus::Active => '\u{25cf}',          // ●
+        MappingStatus::Healthy => '\u{2714}',         // ✔
+        MappingStatus::PortUnreachable => '\u{2716}', // ✖
+        MappingStatus::Unhealthy(_) => '\u{25b2}',    // ▲
+        MappingStatus::Timeout => '\u{25f7}',         // ◷
+        MappingStatus::Starting => '\u{25cc}',        // ◌
+        MappingStatus::Unknown => '?',
+    }
+}
+
+/// Glyph for a disabled mapping.
+pub const DISABLED_GLYPH: char = '\u{25cb}'; // ○
+
+/// Parse a style like `bold underlined #56b4e9 on black`: modifiers, then
+/// an optional foreground colour, then an optional `on <background>`.
+/// Colours are names (`light-red`), `#rrggbb` or 0-255.
+fn parse_style(spec: &str) -> Result<Style> {
+    let mut style = Style::default();
+    let mut words = spec.split_whitespace();
+    let mut background = false;
+    while let Some(word) = words.next() {
+        let modifier = match word.to_ascii_lowercase().as_str() {
+            "bold" => Some(Modifier::BOLD),
+            "dim" => Some(Modifier::DIM),
+            "italic" => Some(Modifier::ITALIC),
+            "underlined" => Some(Modifier::UNDERLINED),
+            "reversed" => Some(Modifier::REVERSED),
+            "on" => {
+                let Some(color) = words.next() else {
+                    bail!("`on` needs a colour after it");
+                };
+                style = style.bg(parse_color(color)?);
+                background = true;
+                None
+            }
+            _ => {
+                if background || style.fg.is_some() {
+                    bail!("Unexpected `{}`", word);
+                }
+                style = style.fg(parse_color(word)?);
+                None
+            }
+        };
+        if let Some(modifier) = modifier {
+            style = style.add_modifier(modifier);
+        }
+    }
+    Ok(style)
+}
+
+fn parse_color(s: &str) -> Result<Color> {
+    s.parse().map_err(|_| anyhow!("Unknown colour `{}`", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> TuiConfig {
+        crate::config::Config::parse(toml).unwrap().tui
+    }
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(parse_style("").unwrap(), Style::default());
+        assert_eq!(
+            parse_style("bold light-red").unwrap(),
+            Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(
+            parse_style("#56b4e9 on 236").unwrap(),
+            Style::default().fg(Color::Rgb(0x56, 0xb4, 0xe9)).bg(Color::Indexed(236))
+        );
+        assert_eq!(parse_style("reversed").unwrap(), Style::default().add_modifier(Modifier::REVERSED));
+        assert!(parse_style("chartreuse").is_err());
+        assert!(parse_style("red green").is_err());
+        assert!(parse_style("red on").is_err());
+    }
+
+    #[test]
+    fn test_builtin_themes() {
+        assert_eq!(Theme::from_config(&config(""), false).unwrap(), Theme::dark());
+        for name in BUILTIN {
+            let toml = format!("[tui]\ntheme = \"{}\"\n", name);
+            assert!(Theme::from_config(&config(&toml), false).is_ok(), "{}", name);
+        }
+        let err = Theme::from_config(&config("[tui]\ntheme = \"solarized\"\n"), false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown theme `solarized`; expected one of dark, light, high-contrast, monochrome"
+        );
+
+        // Monochrome means no colours anywhere
+        let mono = Theme::monochrome();
+        for style in [mono.accent, mono.heading, mono.text, mono.muted, mono.ok, mono.warn, mono.error] {
+            assert_eq!((style.fg, style.bg), (None, None));
+        }
+        // Working and failing must not differ by colour alone in high-contrast
+        let hc = Theme::high_contrast();
+        assert_ne!(hc.ok.fg, Some(Color::Green));
+        assert_ne!(hc.error.fg, Some(Color::Red));
+    }
+
+    #[test]
+    fn test_user_theme() {
+        let tui = config(
+            "[tui]\ntheme = \"mine\"\n\
+             [tui.themes.mine]\nbase = \"light\"\nok = \"bold blue\"\nerror = \"bold #e69f00\"\n",
+        );
+        let theme = Theme::from_config(&tui, false).unwrap();
+        assert_eq!(theme.ok, Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD));
+        assert_eq!(theme.error.fg, Some(Color::Rgb(0xe6, 0x9f, 0x00)));
+        assert_eq!(theme.muted, Theme::light().muted);
+
+        // NO_COLOR wins over the configured theme
+        assert_eq!(Theme::from_config(&tui, true).unwrap(), Theme::monochrome());
+
+        // A broken theme is reported even when it isn't the one in use
+        let tui = config("[tui.themes.broken]\nok = \"sparkly\"\n");
+        let err = Theme::from_config(&tui, false).unwrap_err();
+        assert_eq!(format!("{:#}", err), "Invalid theme [tui.themes.broken]: Invalid style for `ok`: Unknown colour `sparkly`");
+        let tui = config("[tui.themes.x]\nbase = \"x\"\n");
+        assert!(Theme::from_config(&tui, false).is_err());
+    }
+
+    #[test]
+    fn test_status_glyphs_are_distinct() {
+        let statuses = [
+            MappingStatus::Active,
+            MappingStatus::Healthy,
+            MappingStatus::PortUnreachable,
+            MappingStatus::Unhealthy(500),
+            MappingStatus::Timeout,
+            MappingStatus::Starting,
+            MappingStatus::Unknown,
+        ];
+        let mut glyphs: Vec<char> = statuses.iter().map(status_glyph).collect();
+        glyphs.push(DISABLED_GLYPH);
+        let count = glyphs.len();
+        glyphs.sort();
+        glyphs.dedup();
+        assert_eq!(glyphs.len(), count);
+    }
+}
@@ -1,80 +1,191 @@
-use crate::app::{InputMode, Mapping, MappingStatus, PopupField, TuiState};
+use crate::app::{InputMode, Mapping, PopupField, Severity, SortOrder, TuiState};
+use crate::proxy::metrics::{ProxyMetrics, WINDOW_SECS};
+use crate::tui::keymap::{Action, KeyBinding, Keymap};
+use crate::tui::theme::{status_glyph, Theme, DISABLED_GLYPH};
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
-use ratatui::style::{Color, Modifier, Style};
+use ratatui::style::{Modifier, Style};
 use ratatui::text::{Line, Span};
 use ratatui::widgets::{
-    Block, BorderType, Borders, Cell, Clear, Paragraph, Row, Table,
+    Block, BorderType, Borders, Cell, Clear, Padding, Paragraph, Row, Sparkline, Table,
+    TableState,
 };
 use ratatui::Frame;
+use std::time::Duration;
 
-/// Render the entire TUI.
-pub fn draw(f: &mut Frame, state: &TuiState, mappings: &[Mapping]) {
+/// Height of the detail pane, borders included.
+const DETAIL_HEIGHT: u16 = 10;
+
+/// Footer key hints: action and label. Actions without a key are left out.
+const KEY_HINTS: &[(Action, &str)] = &[
+    (Action::Add, "add"),
+    (Action::Edit, "edit"),
+    (Action::Open, "open"),
+    (Action::Copy, "copy"),
+    (Action::Toggle, "toggle"),
+    (Action::Delete, "del"),
+    (Action::Undo, "undo"),
+    (Action::Filter, "filter"),
+    (Action::Sort, "sort"),
+    (Action::Help, "help"),
+    (Action::Quit, "quit"),
+];
+
+/// Where clickable things ended up in the last frame.
+#[derive(Debug, Default)]
+pub struct ClickMap {
+    /// Table body, below the header
+    pub rows: Rect,
+    /// Index of the first row shown in `rows`
+    pub offset: usize,
+    /// Footer key hints and the key each stands for
+    pub hints: Vec<(Rect, KeyBinding)>,
+}
+
+/// Render the entire TUI. Returns what can be clicked where.
+pub fn draw(
+    f: &mut Frame,
+    state: &TuiState,
+    mappings: &[Mapping],
+    keymap: &Keymap,
+    theme: &Theme,
+    metrics: &ProxyMetrics,
+) -> ClickMap {
     let size = f.area();
 
-    // Main layout: table area + status bar
+    // Main layout: table area + detail pane, if there's room + status bar
+    let selected = state.selected_mapping(mappings).map(|(_, m)| m);
+    let detail = selected.filter(|_| size.height >= 5 + DETAIL_HEIGHT + 3);
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(5), Constraint::Length(3)])
+        .constraints([
+            Constraint::Min(5),
+            Constraint::Length(if detail.is_some() { DETAIL_HEIGHT } else { 0 }),
+            Constraint::Length(3),
+        ])
         .split(size);
 
-    draw_table(f, chunks[0], state, mappings);
-    draw_status_bar(f, chunks[1], state, mappings);
+    let clicks = draw_table(f, chunks[0], state, mappings, keymap, theme);
+    if let Some(mapping) = detail {
+        draw_detail(f, chunks[1], state, mapping, metrics, theme);
+    }
+    draw_status_bar(f, chunks[2], state, mappings, keymap, theme);
 
-    if state.mode == InputMode::Adding {
-        draw_popup(f, size, state);
+    if matches!(state.mode, InputMode::Adding | InputMode::Editing(_)) {
+        draw_popup(f, size, state, theme);
     }
+    if state.show_help {
+        draw_help(f, size, state, keymap, theme);
+    }
+    clicks
 }
 
-fn draw_table(f: &mut Frame, area: Rect, state: &TuiState, mappings: &[Mapping]) {
+fn draw_table(
+    f: &mut Frame,
+    area: Rect,
+    state: &TuiState,
+    mappings: &[Mapping],
+    keymap: &Keymap,
+    theme: &Theme,
+) -> ClickMap {
     let header = Row::new(vec![
-        Cell::from("Domain").style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-        Cell::from("Port").style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-        Cell::from("Status").style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+        Cell::from("Domain").style(theme.heading),
+        Cell::from("Port").style(theme.heading),
+        Cell::from("Status").style(theme.heading),
+        Cell::from("Latency").style(theme.heading),
     ])
     .height(1);
 
-    let rows: Vec<Row> = mappings
+    let visible = state.visible(mappings);
+    let rows: Vec<Row> = visible
         .iter()
-        .enumerate()
-        .map(|(i, m)| {
-            let selected = i == state.selected;
+        .map(|m| {
+            let selected = state.selected.as_deref() == Some(m.domain.as_str());
             let prefix = if selected { "\u{25b8} " } else { "  " };
-            let status_style = match m.status {
-                MappingStatus::Active => Style::default().fg(Color::Green),
-                MappingStatus::PortUnreachable => Style::default().fg(Color::Red),
-                MappingStatus::Unknown => Style::default().fg(Color::DarkGray),
-            };
-            let status_text = match m.status {
-                MappingStatus::Active => "\u{25cf} Active",
-                MappingStatus::PortUnreachable => "\u{25cf} Port Unreachable",
-                MappingStatus::Unknown => "\u{25cf} Unknown",
+            let (status_text, status_style) = if m.enabled {
+                (format!("{} {}", status_glyph(&m.status), m.status), theme.status(&m.status))
+            } else {
+                (format!("{} Disabled", DISABLED_GLYPH), theme.muted)
             };
+            let latency = m
+                .latency
+                .map_or("\u{2014}".to_string(), |l| format!("{} ms", l.as_millis()));
 
-            let style = if selected {
-                Style::default().add_modifier(Modifier::BOLD)
+            let mut style = if selected { theme.selected } else { Style::default() };
+            if !m.enabled {
+                style = style.patch(theme.muted).add_modifier(Modifier::DIM);
+            }
+            let status_style = if selected {
+                theme.selected.patch(status_style)
             } else {
-                Style::default()
+                status_style
             };
 
+            let mut domain = vec![Span::raw(format!("{}{}", prefix, m.domain))];
+            if !m.tags.is_empty() {
+                let tags: Vec<String> = m.tags.iter().map(|t| format!("#{}", t)).collect();
+                domain.push(Span::styled(format!("  {}", tags.join(" ")), theme.muted));
+            }
+
             Row::new(vec![
-                Cell::from(format!("{}{}", prefix, m.domain)).style(style),
+                Cell::from(Line::from(domain)).style(style),
                 Cell::from(m.port.to_string()).style(style),
                 Cell::from(status_text).style(status_style),
+                Cell::from(latency).style(style),
             ])
         })
         .collect();
 
-    let title = Line::from(vec![
-        Span::styled(" portmap ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
-    ]);
-    let keyhints = Line::from(vec![
-        Span::styled("[a]", Style::default().fg(Color::Green)),
-        Span::raw("dd "),
-        Span::styled("[d]", Style::default().fg(Color::Red)),
-        Span::raw("el "),
-        Span::styled("[q]", Style::default().fg(Color::Yellow)),
-        Span::raw("uit "),
-    ]);
+    let mut title = vec![
+        Span::styled(" portmap ", theme.accent.add_modifier(Modifier::BOLD)),
+    ];
+    if state.sort != SortOrder::Added {
+        title.push(Span::styled(
+            format!("sorted by {} ", state.sort.label()),
+            theme.muted,
+        ));
+    }
+    if state.mode == InputMode::Filtering || !state.filter.is_empty() {
+        title.push(Span::styled(
+            format!(
+                "/{}{} ({} of {}) ",
+                state.filter,
+                if state.mode == InputMode::Filtering { "\u{2588}" } else { "" },
+                visible.len(),
+                mappings.len()
+            ),
+            theme.warn,
+        ));
+    }
+    let title = Line::from(title);
+
+    // The footer starts just after the bottom-left corner
+    let mut hints = Vec::new();
+    let mut spans = Vec::new();
+    let mut x = area.x + 1;
+    let footer_y = area.bottom().saturating_sub(1);
+    for (action, label) in KEY_HINTS {
+        let Some(binding) = keymap.key(*action) else {
+            continue;
+        };
+        // `[a]dd` when the key is the label's first letter, else `[space] toggle`
+        let key = binding.to_string();
+        let (key, label) = match label.strip_prefix(key.as_str()) {
+            Some(rest) => (format!("[{}]", key), format!("{} ", rest)),
+            None => (format!("[{}]", key), format!(" {} ", label)),
+        };
+        let width = (key.chars().count() + label.chars().count()) as u16;
+        hints.push((Rect::new(x, footer_y, width, 1).intersection(area), binding));
+        x += width;
+        let style = match action {
+            Action::Add => theme.ok,
+            Action::Delete => theme.error,
+            Action::Quit => theme.warn,
+            _ => theme.accent,
+        };
+        spans.push(Span::styled(key, style));
+        spans.push(Span::raw(label));
+    }
+    let keyhints = Line::from(spans);
 
     let block = Block::default()
         .borders(Borders::ALL)
@@ -83,41 +194,225 @@ fn draw_table(f: &mut Frame, area: Rect, state: &TuiState, mappings: &[Mapping])
         .title_bottom(keyhints);
 
     let widths = [
-        Constraint::Percentage(50),
+        Constraint::Percentage(45),
+        Constraint::Percentage(12),
+        Constraint::Percentage(28),
         Constraint::Percentage(15),
-        Constraint::Percentage(35),
     ];
 
-    let table = Table::new(rows, widths)
-        .header(header)
-        .block(block)
-        .highlight_style(Style::default().bg(Color::DarkGray));
+    let inner = block.inner(area);
+    let body = Rect {
+        y: inner.y + 1,
+        height: inner.height.saturating_sub(1),
+        ..inner
+    };
+    let table = Table::new(rows, widths).header(header).block(block);
+
+    // Scrolls to keep the selection in view
+    let mut table_state = TableState::default()
+        .with_offset(state.table_offset)
+        .with_selected(state.selected.as_ref().map(|_| state.selected_row));
+    f.render_stateful_widget(table, area, &mut table_state);
+
+    ClickMap {
+        rows: body,
+        offset: table_state.offset(),
+        hints,
+    }
+}
+
+/// Upstream, uptime and proxy traffic for the selected mapping.
+fn draw_detail(
+    f: &mut Frame,
+    area: Rect,
+    state: &TuiState,
+    mapping: &Mapping,
+    metrics: &ProxyMetrics,
+    theme: &Theme,
+) {
+    let snapshot = metrics.snapshot(&mapping.domain);
+    let changed = state
+        .status_changed
+        .get(&mapping.domain)
+        .map(|at| at.elapsed());
+
+    let none = || "\u{2014}".to_string();
+    let uptime = match changed {
+        _ if !mapping.enabled => "disabled".to_string(),
+        Some(elapsed) if mapping.status.is_up() => format_duration(elapsed),
+        _ => none(),
+    };
+    let latency = match (snapshot.p50, snapshot.p95) {
+        (Some(p50), Some(p95)) => {
+            format!("p50 {} ms, p95 {} ms", p50.as_millis(), p95.as_millis())
+        }
+        _ => none(),
+    };
+    let errors = format!("{} ({:.1}%)", snapshot.errors, snapshot.error_rate() * 100.0);
+    let errors_style = if snapshot.errors > 0 { theme.error } else { Style::default() };
+
+    let field = |label: &str, value: String, style: Style| {
+        Line::from(vec![
+            Span::styled(format!("{:<10}", label), theme.muted),
+            Span::styled(value, style),
+        ])
+    };
+    let left = vec![
+        field("Upstream", format!("http://localhost:{}", mapping.port), Style::default()),
+        field(
+            "Health",
+            mapping
+                .health
+                .as_ref()
+                .map_or("TCP connect".to_string(), |h| h.to_string()),
+            Style::default(),
+        ),
+        field("Uptime", uptime, theme.ok),
+        field(
+            "Changed",
+            changed.map_or_else(none, |elapsed| format!("{} ago", format_duration(elapsed))),
+            Style::default(),
+        ),
+    ];
+    let right = vec![
+        field("Requests", snapshot.requests.to_string(), Style::default()),
+        field("Errors", errors, errors_style),
+        field("Latency", latency, Style::default()),
+        field(
+            "Tags",
+            if mapping.tags.is_empty() { none() } else { mapping.tags.join(", ") },
+            Style::default(),
+        ),
+    ];
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .title(Line::from(Span::styled(
+            format!(" {} ", mapping.domain),
+            theme.accent.add_modifier(Modifier::BOLD),
+        )))
+        .padding(Padding::horizontal(1));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
 
-    f.render_widget(table, area);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Length(1), Constraint::Min(1)])
+        .split(inner);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[0]);
+    f.render_widget(Paragraph::new(left), columns[0]);
+    f.render_widget(Paragraph::new(right), columns[1]);
+
+    // As many of the latest seconds as fit, one per column
+    let shown = (rows[2].width as usize).min(WINDOW_SECS);
+    let per_second = &snapshot.per_second[WINDOW_SECS - shown..];
+    let peak = per_second.iter().max().copied().unwrap_or(0);
+    f.render_widget(
+        Paragraph::new(Span::styled(
+            format!(
+                "Requests/s, last {} (peak {})",
+                format_duration(Duration::from_secs(shown as u64)),
+                peak
+            ),
+            theme.muted,
+        )),
+        rows[1],
+    );
+    f.render_widget(Sparkline::default().data(per_second).style(theme.accent), rows[2]);
 }
 
-fn draw_status_bar(f: &mut Frame, area: Rect, state: &TuiState, mappings: &[Mapping]) {
+/// `45s`, `5m 12s` or `2h 3m`.
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn draw_status_bar(
+    f: &mut Frame,
+    area: Rect,
+    state: &TuiState,
+    mappings: &[Mapping],
+    keymap: &Keymap,
+    theme: &Theme,
+) {
+    let key = |action| keymap.key(action).map_or("?".to_string(), |k| k.to_string());
     let msg = state
         .status_message
         .as_deref()
         .unwrap_or("");
 
-    let status = Line::from(vec![
+    let proxy = if state.draining {
+        let n = state.proxy.active_connections;
         Span::styled(
-            " Proxy running on :80",
-            Style::default().fg(Color::Green),
-        ),
+            format!(
+                " Draining {} connection{}\u{2026} ({} again to force quit)",
+                n,
+                if n == 1 { "" } else { "s" },
+                key(Action::Quit)
+            ),
+            theme.warn,
+        )
+    } else {
+        Span::styled(
+            format!(
+                " Proxy running on {} ({})",
+                state
+                    .session
+                    .listen
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                state.session.mode.label()
+            ),
+            theme.ok,
+        )
+    };
+    let mut spans = vec![
+        proxy,
         Span::raw(" \u{2502} "),
         Span::styled(
             format!("{} mapping{}", mappings.len(), if mappings.len() == 1 { "" } else { "s" }),
-            Style::default().fg(Color::Cyan),
+            theme.accent,
         ),
-        if !msg.is_empty() {
-            Span::raw(format!(" \u{2502} {}", msg))
-        } else {
-            Span::raw("")
-        },
-    ]);
+    ];
+    if let Some(release) = &state.update {
+        spans.push(Span::raw(" \u{2502} "));
+        spans.push(Span::styled(
+            match keymap.key(Action::Update) {
+                // A root-owned install can't be replaced from here
+                _ if !state.session.self_update => format!(
+                    "{} available \u{2014} rerun the installer to update",
+                    release.tag_name
+                ),
+                Some(k) => format!("{} available \u{2014} press {} to update", release.tag_name, k),
+                None => format!("{} available", release.tag_name),
+            },
+            theme.notice,
+        ));
+    }
+    if let Some(drift) = &state.hosts_drift {
+        spans.push(Span::raw(" \u{2502} "));
+        spans.push(Span::styled(
+            format!(
+                "\u{26a0} hosts file drift ({}\u{00d7}): {}",
+                drift.count, drift.message
+            ),
+            theme.warn,
+        ));
+    }
+    if !msg.is_empty() {
+        spans.push(Span::raw(format!(" \u{2502} {}", msg)));
+    }
+    let status = Line::from(spans);
 
     let block = Block::default()
         .borders(Borders::ALL)
@@ -127,9 +422,9 @@ fn draw_status_bar(f: &mut Frame, area: Rect, state: &TuiState, mappings: &[Mapp
     f.render_widget(paragraph, area);
 }
 
-fn draw_popup(f: &mut Frame, area: Rect, state: &TuiState) {
+fn draw_popup(f: &mut Frame, area: Rect, state: &TuiState, theme: &Theme) {
     let popup_width = 50u16.min(area.width.saturating_sub(4));
-    let popup_height = 9u16.min(area.height.saturating_sub(4));
+    let popup_height = 16u16.min(area.height.saturating_sub(4));
     let x = (area.width.saturating_sub(popup_width)) / 2;
     let y = (area.height.saturating_sub(popup_height)) / 2;
     let popup_area = Rect::new(x, y, popup_width, popup_height);
@@ -137,14 +432,18 @@ fn draw_popup(f: &mut Frame, area: Rect, state: &TuiState) {
     // Clear the area behind the popup
     f.render_widget(Clear, popup_area);
 
+    let title = match state.mode {
+        InputMode::Editing(_) => " Edit Mapping ",
+        _ => " Add Mapping ",
+    };
     let block = Block::default()
         .title(Line::from(Span::styled(
-            " Add Mapping ",
-            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            title,
+            theme.accent.add_modifier(Modifier::BOLD),
         )))
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
-        .border_style(Style::default().fg(Color::Cyan));
+        .border_style(theme.accent);
 
     let inner = block.inner(popup_area);
     f.render_widget(block, popup_area);
@@ -154,38 +453,47 @@ fn draw_popup(f: &mut Frame, area: Rect, state: &TuiState) {
         .constraints([
             Constraint::Length(1), // domain label
             Constraint::Length(1), // domain input
-            Constraint::Length(1), // spacing
+            Constraint::Length(1), // domain issue
             Constraint::Length(1), // port label
             Constraint::Length(1), // port input
+            Constraint::Length(1), // port issue
+            Constraint::Length(1), // health label
+            Constraint::Length(1), // health input
+            Constraint::Length(1), // health issue
+            Constraint::Length(1), // tags label
+            Constraint::Length(1), // tags input
+            Constraint::Length(1), // tags issue
             Constraint::Min(0),   // hints
         ])
         .split(inner);
 
     let domain_focused = state.popup_field == PopupField::Domain;
     let port_focused = state.popup_field == PopupField::Port;
+    let health_focused = state.popup_field == PopupField::Health;
+    let tags_focused = state.popup_field == PopupField::Tags;
 
     // Domain field
     let domain_label = Paragraph::new(Line::from(vec![
         Span::styled(
             "Domain: ",
             if domain_focused {
-                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+                theme.accent.add_modifier(Modifier::BOLD)
             } else {
-                Style::default().fg(Color::DarkGray)
+                theme.muted
             },
         ),
     ]));
     f.render_widget(domain_label, chunks[0]);
 
     let cursor_style = if domain_focused {
-        Style::default().fg(Color::White)
+        theme.text
     } else {
-        Style::default().fg(Color::DarkGray)
+        theme.muted
     };
 
     let domain_value = Paragraph::new(Line::from(vec![
-        Span::styled(&state.domain_input, Style::default().fg(Color::White)),
-        Span::styled(".localhost", Style::default().fg(Color::DarkGray)),
+        Span::styled(&state.domain_input, theme.text),
+        Span::styled(".localhost", theme.muted),
     ]))
     .style(cursor_style);
     f.render_widget(domain_value, chunks[1]);
@@ -202,9 +510,9 @@ fn draw_popup(f: &mut Frame, area: Rect, state: &TuiState) {
         Span::styled(
             "Port: ",
             if port_focused {
-                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+                theme.accent.add_modifier(Modifier::BOLD)
             } else {
-                Style::default().fg(Color::DarkGray)
+                theme.muted
             },
         ),
     ]));
@@ -212,7 +520,7 @@ fn draw_popup(f: &mut Frame, area: Rect, state: &TuiState) {
 
     let port_value = Paragraph::new(Line::from(Span::styled(
         &state.port_input,
-        Style::default().fg(Color::White),
+        theme.text,
     )));
     f.render_widget(port_value, chunks[4]);
 
@@ -223,17 +531,160 @@ fn draw_popup(f: &mut Frame, area: Rect, state: &TuiState) {
         ));
     }
 
+    // Health check field
+    let health_label = Paragraph::new(Line::from(vec![
+        Span::styled(
+            "Health check: ",
+            if health_focused {
+                theme.accent.add_modifier(Modifier::BOLD)
+            } else {
+                theme.muted
+            },
+        ),
+    ]));
+    f.render_widget(health_label, chunks[6]);
+
+    let health_value = if state.health_input.is_empty() && !health_focused {
+        Line::from(Span::styled("optional", theme.muted))
+    } else if state.health_input.is_empty() {
+        Line::from(Span::styled(
+            "/healthz 200-299 2s",
+            theme.muted,
+        ))
+    } else {
+        Line::from(Span::styled(&state.health_input, theme.text))
+    };
+    f.render_widget(Paragraph::new(health_value), chunks[7]);
+
+    if health_focused {
+        f.set_cursor_position((
+            chunks[7].x + state.health_input.len() as u16,
+            chunks[7].y,
+        ));
+    }
+
+    // Tags field
+    let tags_label = Paragraph::new(Line::from(vec![
+        Span::styled(
+            "Tags: ",
+            if tags_focused {
+                theme.accent.add_modifier(Modifier::BOLD)
+            } else {
+                theme.muted
+            },
+        ),
+    ]));
+    f.render_widget(tags_label, chunks[9]);
+
+    let tags_value = if state.tags_input.is_empty() && !tags_focused {
+        Line::from(Span::styled("optional", theme.muted))
+    } else if state.tags_input.is_empty() {
+        Line::from(Span::styled("frontend team-a", theme.muted))
+    } else {
+        Line::from(Span::styled(&state.tags_input, theme.text))
+    };
+    f.render_widget(Paragraph::new(tags_value), chunks[10]);
+
+    if tags_focused {
+        f.set_cursor_position((
+            chunks[10].x + state.tags_input.chars().count() as u16,
+            chunks[10].y,
+        ));
+    }
+
+    for (field, chunk) in [
+        (PopupField::Domain, chunks[2]),
+        (PopupField::Port, chunks[5]),
+        (PopupField::Health, chunks[8]),
+        (PopupField::Tags, chunks[11]),
+    ] {
+        if let Some(issue) = state.input_issues.iter().find(|i| i.field == field) {
+            let (glyph, style) = match issue.severity {
+                Severity::Error => ('\u{2716}', theme.error),
+                Severity::Warning => ('\u{25b2}', theme.warn),
+            };
+            f.render_widget(
+                Paragraph::new(Span::styled(format!("{} {}", glyph, issue.message), style)),
+                chunk,
+            );
+        }
+    }
+
     // Hints
-    if chunks[5].height > 0 {
+    if chunks[12].height > 0 {
         let hints = Paragraph::new(Line::from(vec![
-            Span::styled("Tab", Style::default().fg(Color::Yellow)),
+            Span::styled("Tab", theme.heading),
             Span::raw(" switch  "),
-            Span::styled("Enter", Style::default().fg(Color::Green)),
+            Span::styled("Enter", theme.ok),
             Span::raw(" submit  "),
-            Span::styled("Esc", Style::default().fg(Color::Red)),
+            Span::styled("Esc", theme.error),
             Span::raw(" cancel"),
         ]))
-        .style(Style::default().fg(Color::DarkGray));
-        f.render_widget(hints, chunks[5]);
+        .style(theme.muted);
+        f.render_widget(hints, chunks[12]);
+    }
+}
+
+/// Every action in the current mode and its keys.
+fn draw_help(f: &mut Frame, area: Rect, state: &TuiState, keymap: &Keymap, theme: &Theme) {
+    let entries = keymap.help(&state.mode);
+    let key_width = entries.iter().map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+    let text_width = entries
+        .iter()
+        .map(|(_, d)| key_width + 2 + d.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let width = (text_width as u16 + 4).min(area.width);
+    let height = (entries.len() as u16 + 2).min(area.height);
+    let x = area.x + (area.width - width) / 2;
+    let y = area.y + (area.height - height) / 2;
+    let help_area = Rect::new(x, y, width, height);
+
+    let lines: Vec<Line> = entries
+        .into_iter()
+        .map(|(keys, description)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<width$}  ", keys, width = key_width),
+                    theme.heading,
+                ),
+                Span::raw(description),
+            ])
+        })
+        .collect();
+
+    let title = match state.mode {
+        InputMode::Normal => " Keys ",
+        InputMode::Adding => " Keys: Add Mapping ",
+        InputMode::Editing(_) => " Keys: Edit Mapping ",
+        InputMode::Filtering => " Keys: Filter ",
+        InputMode::ConfirmDelete(_) => " Keys: Delete ",
+    };
+    let block = Block::default()
+        .title(Line::from(Span::styled(
+            title,
+            theme.accent.add_modifier(Modifier::BOLD),
+        )))
+        .title_bottom(Line::from(" any key closes ").right_aligned())
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(theme.accent)
+        .padding(Padding::horizontal(1));
+
+    f.render_widget(Clear, help_area);
+    f.render_widget(Paragraph::new(lines).block(block), help_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(312)), "5m 12s");
+        assert_eq!(format_duration(Duration::from_secs(7380)), "2h 3m");
     }
 }
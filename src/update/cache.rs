@@ -0,0 +1,101 @@
+use crate::config::{user_home, UpdateChannel};
+use crate::update::release::Release;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a check result is trusted before the release host is asked again.
+pub const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The outcome of the last update check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedCheck {
+    /// Unix time of the check, in seconds
+    pub checked_at: u64,
+    pub channel: UpdateChannel,
+    /// Newer release found at the time, if any
+    pub release: Option<Release>,
+}
+
+impl CachedCheck {
+    pub fn new(channel: UpdateChannel, release: Option<Release>) -> Self {
+        Self {
+            checked_at: unix_now(),
+            channel,
+            release,
+        }
+    }
+
+    /// Still usable for `channel` at `now` (Unix seconds).
+    pub fn is_fresh(&self, channel: UpdateChannel, now: u64) -> bool {
+        self.channel == channel && now.saturating_sub(self.checked_at) < MAX_AGE.as_secs()
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// `$XDG_CACHE_HOME/portmap/update.json`, falling back to `~/.cache`.
+pub fn cache_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => user_home()?.join(".cache"),
+    };
+    Some(dir.join("portmap").join("update.json"))
+}
+
+/// Read a cached check; a missing or unreadable cache is simply absent.
+pub fn load(path: &Path) -> Option<CachedCheck> {
+    let content = std::fs::read(path).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+pub fn store(path: &Path, check: &CachedCheck) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, serde_json::to_vec(check)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_round_trip_and_freshness() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("portmap").join("update.json");
+        assert!(load(&path).is_none());
+
+        let release = Release {
+            tag_name: "v0.2.0".to_string(),
+            draft: false,
+            prerelease: false,
+            assets: Vec::new(),
+        };
+        let check = CachedCheck::new(UpdateChannel::Stable, Some(release));
+        store(&path, &check).unwrap();
+
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.release.as_ref().unwrap().tag_name, "v0.2.0");
+        let now = loaded.checked_at;
+        assert!(loaded.is_fresh(UpdateChannel::Stable, now + 60));
+        assert!(!loaded.is_fresh(UpdateChannel::Stable, now + MAX_AGE.as_secs()));
+        assert!(!loaded.is_fresh(UpdateChannel::Prerelease, now));
+    }
+
+    #[test]
+    fn test_corrupt_cache_ignored() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("update.json");
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(load(&path).is_none());
+    }
+}
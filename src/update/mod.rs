@@ -0,0 +1,653 @@
+//! Self-updater.
+//!
+//! Releases are looked up on a GitHub-style "list releases" endpoint. The
+//! archive for this build's target is only installed once it matches its
+//! published SHA-256 and minisign signature. Release builds embed the
+//! project's public key; a build without one refuses to install updates.
+//! All of this runs as the invoking user, never as root.
+//!
+//! The check runs in the background and is cached for a day; installing
+//! is left to the user (`U` in the TUI) and takes effect on the next launch.
+
+pub mod cache;
+pub mod release;
+mod verify;
+
+use crate::config::{UpdateChannel, UpdateConfig};
+use crate::privilege::is_root;
+use anyhow::{anyhow, bail, Context, Result};
+use cache::CachedCheck;
+use release::Release;
+use semver::Version;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Set to anything but `0` to disable update checks.
+const NO_UPDATE_ENV: &str = "PORTMAP_NO_UPDATE";
+/// Overrides `update.release_url`.
+const RELEASE_URL_ENV: &str = "PORTMAP_RELEASE_URL";
+/// The project's minisign key, embedded by the release workflow.
+const BUILTIN_PUBLIC_KEY: Option<&str> = option_env!("PORTMAP_MINISIGN_PUBKEY");
+/// Release archives are a few MB; anything far larger is not ours.
+const MAX_DOWNLOAD: u64 = 64 * 1024 * 1024;
+
+pub struct Updater {
+    release_url: String,
+    channel: UpdateChannel,
+    public_key: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Updater {
+    pub fn from_config(config: &UpdateConfig) -> Self {
+        let release_url =
+            std::env::var(RELEASE_URL_ENV).unwrap_or_else(|_| config.release_url.clone());
+        let public_key = config
+            .public_key
+            .clone()
+            .or_else(|| BUILTIN_PUBLIC_KEY.map(str::to_string));
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("portmap/", env!("CARGO_PKG_VERSION")))
+            .build();
+        Self {
+            release_url,
+            channel: config.channel,
+            public_key,
+            agent,
+        }
+    }
+
+    fn get(&self, url: &str) -> Result<Vec<u8>> {
+        let response = self
+            .agent
+            .get(url)
+            .call()
+            .with_context(|| format!("GET {} failed", url))?;
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_DOWNLOAD)
+            .read_to_end(&mut body)?;
+        Ok(body)
+    }
+
+    /// The newest release on the configured channel, if it's newer than `current`.
+    pub fn latest(&self, current: &Version) -> Result<Option<Release>> {
+        let body = self.get(&self.release_url)?;
+        let releases: Vec<Release> =
+            serde_json::from_slice(&body).context("Unexpected release list format")?;
+        Ok(release::select(&releases, self.channel, current).cloned())
+    }
+
+    /// Download and verify the archive for `target`, returning the binary in it.
+    pub fn download(&self, release: &Release, target: &str) -> Result<Vec<u8>> {
+        let name = release::archive_name(target);
+        let archive = release
+            .asset(&name)
+            .ok_or_else(|| anyhow!("{} has no build for {}", release.tag_name, target))?;
+        let key = self.public_key.as_deref().ok_or_else(|| {
+            anyhow!(
+                "This build has no minisign public key to verify updates with; \
+                 set update.public_key or install a release build"
+            )
+        })?;
+        let checksum = release
+            .asset(&format!("{}.sha256", name))
+            .ok_or_else(|| anyhow!("{} publishes no checksum for {}", release.tag_name, name))?;
+        let signature = release
+            .asset(&format!("{}.minisig", name))
+            .ok_or_else(|| anyhow!("{} publishes no signature for {}", release.tag_name, name))?;
+
+        let data = self.get(&archive.browser_download_url)?;
+        let checksum = String::from_utf8(self.get(&checksum.browser_download_url)?)
+            .context("checksum file is not text")?;
+        verify::verify_sha256(&data, &checksum, &name)?;
+        let signature = String::from_utf8(self.get(&signature.browser_download_url)?)
+            .context("signature file is not text")?;
+        verify::verify_minisign(&data, &signature, key)?;
+
+        extract_binary(&data)
+    }
+
+    /// Replace the binary at `exe` with the one from `release`.
+    pub fn install(&self, release: &Release, target: &str, exe: &Path) -> Result<()> {
+        check_replaceable(exe)?;
+        let binary = self.download(release, target)?;
+        replace_binary(exe, &binary)
+    }
+}
+
+/// Pull `portmap` out of a `.tar.gz` release archive.
+fn extract_binary(archive: &[u8]) -> Result<Vec<u8>> {
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.file_name().is_some_and(|n| n == "portmap") {
+            let mut binary = Vec::new();
+            entry.read_to_end(&mut binary)?;
+            return Ok(binary);
+        }
+    }
+    bail!("release archive does not contain a portmap binary")
+}
+
+/// Where the version replaced by the last update is kept.
+pub fn previous_path(exe: &Path) -> PathBuf {
+    exe.with_extension("prev")
+}
+
+/// Fail unless `exe` can be replaced in place: updating stages the new
+/// binary and keeps the old one next to it, so its directory must be
+/// writable (it isn't for a root-owned install like `/usr/local/bin`).
+pub fn check_replaceable(exe: &Path) -> Result<()> {
+    let dir = exe.parent().context("binary has no parent directory")?;
+    let probe = dir.join(format!(".portmap-update-{}", std::process::id()));
+    match std::fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            Ok(())
+        }
+        Err(e) => Err(e).with_context(|| {
+            format!(
+                "Cannot write to {}; rerun the installer to update, or reinstall \
+                 into a directory you own (e.g. INSTALL_DIR=~/.local/bin)",
+                dir.display()
+            )
+        }),
+    }
+}
+
+/// Why the running binary can't update itself (`U` in the TUI), if it can't.
+pub fn self_update_problem() -> Option<String> {
+    if is_root() {
+        return Some("Not updating as root; run portmap without sudo to update".to_string());
+    }
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => return Some(format!("Can't locate the portmap binary: {}", e)),
+    };
+    check_replaceable(&exe).err().map(|e| format!("{:#}", e))
+}
+
+/// Swap `binary` in for `exe`, keeping the old one as [`previous_path`].
+///
+/// The new binary has to pass `--version` before it's kept; if it doesn't,
+/// the previous one is put back.
+fn replace_binary(exe: &Path, binary: &[u8]) -> Result<()> {
+    let staged = exe.with_extension("new");
+    std::fs::write(&staged, binary)
+        .with_context(|| format!("Cannot write to {}", staged.display()))?;
+    std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+
+    let previous = previous_path(exe);
+    let swap = std::fs::copy(exe, &previous).and_then(|_| std::fs::rename(&staged, exe));
+    if let Err(e) = swap {
+        let _ = std::fs::remove_file(&staged);
+        return Err(e).context("Failed to swap in the new binary");
+    }
+
+    if let Err(e) = self_test(exe) {
+        std::fs::rename(&previous, exe).context("Failed to restore the previous binary")?;
+        bail!("new binary failed its self-test ({}); kept the current version", e);
+    }
+    Ok(())
+}
+
+/// Check that the binary at `exe` starts at all: `--version` must succeed.
+fn self_test(exe: &Path) -> Result<()> {
+    let output = Command::new(exe)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .context("could not run it")?;
+    if !output.status.success() {
+        bail!("`--version` exited with {}", output.status);
+    }
+    Ok(())
+}
+
+/// Put the binary replaced by the last update back in place of `exe`.
+///
+/// The two are swapped, so rolling back twice returns to the newer one.
+pub fn rollback(exe: &Path) -> Result<()> {
+    let previous = previous_path(exe);
+    if !previous.exists() {
+        bail!("no previous version to roll back to ({} not found)", previous.display());
+    }
+    let current = exe.with_extension("rollback");
+    std::fs::rename(exe, &current)
+        .with_context(|| format!("Cannot replace {}", exe.display()))?;
+    if let Err(e) = std::fs::rename(&previous, exe) {
+        let _ = std::fs::rename(&current, exe);
+        return Err(e).context("Failed to restore the previous binary");
+    }
+    std::fs::rename(&current, &previous)?;
+    Ok(())
+}
+
+fn current_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("crate version is valid semver")
+}
+
+/// Whether update checks are wanted for this launch.
+pub fn enabled(config: &UpdateConfig) -> bool {
+    let opted_out = std::env::var(NO_UPDATE_ENV).is_ok_and(|v| !v.is_empty() && v != "0");
+    config.enabled && !opted_out
+}
+
+/// A release newer than `current`, answered from the cache at `cache_path`
+/// when it is less than a day old.
+pub fn find_update(
+    config: &UpdateConfig,
+    current: &Version,
+    cache_path: Option<&Path>,
+) -> Result<Option<Release>> {
+    if let Some(cached) = cache_path.and_then(cache::load) {
+        if cached.is_fresh(config.channel, cache::unix_now()) {
+            // Ignore it if this binary has caught up in the meantime
+            return Ok(cached
+                .release
+                .filter(|r| r.version().is_some_and(|v| v > *current)));
+        }
+    }
+
+    let release = Updater::from_config(config).latest(current)?;
+    if let Some(path) = cache_path {
+        cache::store(path, &CachedCheck::new(config.channel, release.clone()))?;
+    }
+    Ok(release)
+}
+
+/// Check for updates off the UI thread; a newer release is published on `tx`.
+///
+/// Failures (offline, rate-limited) are silent: this only ever adds a hint
+/// to the status bar.
+pub fn spawn_update_check(config: UpdateConfig, tx: watch::Sender<Option<Release>>) {
+    if !enabled(&config) || is_root() {
+        return;
+    }
+    tokio::task::spawn_blocking(move || {
+        let path = cache::cache_path();
+        if let Ok(Some(release)) = find_update(&config, &current_version(), path.as_deref()) {
+            let _ = tx.send(Some(release));
+        }
+    });
+}
+
+/// Install `release` over the running binary; it's used from the next launch.
+///
+/// Refuses as root: replacing a user-owned binary as root (or a root-owned
+/// one at all) is not our call.
+pub fn install(config: &UpdateConfig, release: &Release) -> Result<()> {
+    if is_root() {
+        bail!("not updating as root; run portmap without sudo (or with privileges.drop) to update");
+    }
+    let exe = std::env::current_exe()?;
+    Updater::from_config(config).install(release, &release::target()?, &exe)?;
+    // Don't offer the version that's now installed
+    if let Some(path) = cache::cache_path() {
+        let _ = cache::store(&path, &CachedCheck::new(config.channel, None));
+    }
+    Ok(())
+}
+
+/// `portmap update [--rollback]`: update (or roll back) right away.
+pub fn run_update_command(config: &UpdateConfig, rollback_requested: bool) -> Result<()> {
+    if is_root() {
+        bail!("run `portmap update` without sudo, as the user who installed portmap");
+    }
+    let exe = std::env::current_exe()?;
+    if rollback_requested {
+        rollback(&exe)?;
+        println!("Rolled back to the previous version of portmap");
+        return Ok(());
+    }
+
+    let current = current_version();
+    let Some(release) = Updater::from_config(config).latest(&current)? else {
+        println!("portmap {} is up to date", current);
+        return Ok(());
+    };
+    println!("Updating portmap {} \u{2192} {}...", current, release.tag_name);
+    install(config, &release)?;
+    println!(
+        "Updated to {} (`portmap update --rollback` restores {})",
+        release.tag_name, current
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use base64::Engine;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    const TARGET: &str = "x86_64-unknown-linux-musl";
+    const OLD: &[u8] = b"#!/bin/sh\necho portmap 0.1.0\n";
+    const NEW: &[u8] = b"#!/bin/sh\necho portmap 99.0.0\n";
+
+    fn installed_exe(dir: &TempDir) -> PathBuf {
+        let exe = dir.path().join("portmap");
+        std::fs::write(&exe, OLD).unwrap();
+        std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+        exe
+    }
+
+    type Files = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    /// A minimal HTTP server standing in for the release host.
+    fn serve(files: Files) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // Skip the headers
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or("/");
+                let (status, body) = match files.lock().unwrap().get(path) {
+                    Some(body) => ("200 OK", body.clone()),
+                    None => ("404 Not Found", Vec::new()),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        base
+    }
+
+    fn archive(binary: &[u8]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(binary.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append_data(&mut header, "portmap", binary).unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Key id shared by the test key pair and its signatures.
+    const KEY_ID: [u8; 8] = *b"portmap!";
+
+    fn signing_key(seed: u8) -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap()
+    }
+
+    /// Minisign public key for `signing_key(seed)`.
+    fn public_key(seed: u8) -> String {
+        let mut bin = b"Ed".to_vec();
+        bin.extend(KEY_ID);
+        bin.extend(signing_key(seed).public_key().as_ref());
+        base64::engine::general_purpose::STANDARD.encode(bin)
+    }
+
+    /// A `.minisig` file for `data`, as `minisign -S` would write it.
+    fn sign(seed: u8, data: &[u8]) -> String {
+        let key = signing_key(seed);
+        let signature = key.sign(data);
+        let mut bin = b"Ed".to_vec();
+        bin.extend(KEY_ID);
+        bin.extend(signature.as_ref());
+        let trusted = "timestamp:0\tfile:portmap.tar.gz";
+        let mut global = signature.as_ref().to_vec();
+        global.extend(trusted.as_bytes());
+        let b64 = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+        format!(
+            "untrusted comment: signature from test key\n{}\ntrusted comment: {}\n{}\n",
+            b64(&bin),
+            trusted,
+            b64(key.sign(&global).as_ref())
+        )
+    }
+
+    /// Publish one release signed with the test key, returning the release
+    /// list URL.
+    fn publish(tag: &str, archive: Vec<u8>, checksum: String) -> String {
+        let signature = sign(1, &archive);
+        publish_signed(tag, archive, checksum, Some(signature))
+    }
+
+    fn publish_signed(
+        tag: &str,
+        archive: Vec<u8>,
+        checksum: String,
+        signature: Option<String>,
+    ) -> String {
+        let files = Files::default();
+        let base = serve(files.clone());
+        let name = release::archive_name(TARGET);
+        let mut assets = vec![
+            serde_json::json!({ "name": name, "browser_download_url": format!("{}/dl/{}", base, name) }),
+            serde_json::json!({ "name": format!("{}.sha256", name), "browser_download_url": format!("{}/dl/{}.sha256", base, name) }),
+        ];
+        if signature.is_some() {
+            assets.push(serde_json::json!({ "name": format!("{}.minisig", name), "browser_download_url": format!("{}/dl/{}.minisig", base, name) }));
+        }
+        let releases = serde_json::json!([{
+            "tag_name": tag,
+            "prerelease": false,
+            "assets": assets,
+        }]);
+
+        let mut files = files.lock().unwrap();
+        files.insert("/releases".to_string(), releases.to_string().into_bytes());
+        files.insert(format!("/dl/{}", name), archive);
+        files.insert(format!("/dl/{}.sha256", name), checksum.into_bytes());
+        if let Some(signature) = signature {
+            files.insert(format!("/dl/{}.minisig", name), signature.into_bytes());
+        }
+        format!("{}/releases", base)
+    }
+
+    fn updater(release_url: String) -> Updater {
+        let config = UpdateConfig {
+            release_url,
+            public_key: Some(public_key(1)),
+            ..UpdateConfig::default()
+        };
+        Updater::from_config(&config)
+    }
+
+    #[test]
+    fn test_update_from_local_release_host() {
+        let data = archive(NEW);
+        let checksum = format!(
+            "{}  {}\n",
+            verify::sha256_hex(&data),
+            release::archive_name(TARGET)
+        );
+        let updater = updater(publish("v99.0.0", data, checksum));
+
+        let current = Version::parse("0.1.0").unwrap();
+        let release = updater.latest(&current).unwrap().unwrap();
+        assert_eq!(release.tag_name, "v99.0.0");
+
+        let dir = TempDir::new().unwrap();
+        let exe = installed_exe(&dir);
+        updater.install(&release, TARGET, &exe).unwrap();
+        assert_eq!(std::fs::read(&exe).unwrap(), NEW);
+        assert_eq!(std::fs::read(previous_path(&exe)).unwrap(), OLD);
+        assert_eq!(
+            std::fs::metadata(&exe).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+
+        let current = Version::parse("99.0.0").unwrap();
+        assert!(updater.latest(&current).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_checksum_mismatch_keeps_binary() {
+        let data = archive(NEW);
+        let checksum = verify::sha256_hex(b"something else");
+        let updater = updater(publish("v99.0.0", data, checksum));
+
+        let current = Version::parse("0.1.0").unwrap();
+        let release = updater.latest(&current).unwrap().unwrap();
+        let dir = TempDir::new().unwrap();
+        let exe = installed_exe(&dir);
+
+        let err = updater.install(&release, TARGET, &exe).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"));
+        assert_eq!(std::fs::read(&exe).unwrap(), OLD);
+    }
+
+    #[test]
+    fn test_failed_self_test_restores_previous() {
+        let dir = TempDir::new().unwrap();
+        let exe = installed_exe(&dir);
+
+        let err = replace_binary(&exe, b"#!/bin/sh\nexit 1\n").unwrap_err();
+        assert!(err.to_string().contains("self-test"));
+        assert_eq!(std::fs::read(&exe).unwrap(), OLD);
+    }
+
+    #[test]
+    fn test_read_only_install_dir_is_refused_before_download() {
+        // Nothing listens here: the check has to fail before any request
+        let updater = updater("http://127.0.0.1:9/releases".to_string());
+        let release: Release = serde_json::from_value(serde_json::json!({
+            "tag_name": "v99.0.0",
+            "prerelease": false,
+            "assets": [],
+        }))
+        .unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let exe = installed_exe(&dir);
+        assert!(check_replaceable(&exe).is_ok());
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o555)).unwrap();
+        // Root can write anywhere; /proc stands in for a directory it can't
+        let exe = if is_root() { PathBuf::from("/proc/portmap") } else { exe };
+
+        let err = updater.install(&release, TARGET, &exe).unwrap_err();
+        assert!(err.to_string().contains("rerun the installer"));
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(std::fs::read(dir.path().join("portmap")).unwrap(), OLD);
+    }
+
+    #[test]
+    fn test_rollback_swaps_versions() {
+        let dir = TempDir::new().unwrap();
+        let exe = installed_exe(&dir);
+        assert!(rollback(&exe).is_err());
+
+        replace_binary(&exe, NEW).unwrap();
+        rollback(&exe).unwrap();
+        assert_eq!(std::fs::read(&exe).unwrap(), OLD);
+        assert_eq!(std::fs::read(previous_path(&exe)).unwrap(), NEW);
+
+        // Rolling back again returns to the newer version
+        rollback(&exe).unwrap();
+        assert_eq!(std::fs::read(&exe).unwrap(), NEW);
+    }
+
+    #[test]
+    fn test_find_update_uses_fresh_cache() {
+        let dir = TempDir::new().unwrap();
+        let cache_path = dir.path().join("update.json");
+        let current = Version::parse("0.1.0").unwrap();
+
+        // Nothing listens here: a fresh cache must not touch the network
+        let offline = UpdateConfig {
+            release_url: "http://127.0.0.1:9/releases".to_string(),
+            ..UpdateConfig::default()
+        };
+        cache::store(&cache_path, &CachedCheck::new(UpdateChannel::Stable, None)).unwrap();
+        assert!(find_update(&offline, &current, Some(&cache_path))
+            .unwrap()
+            .is_none());
+
+        // A stale one is refreshed from the release host
+        let mut stale = CachedCheck::new(UpdateChannel::Stable, None);
+        stale.checked_at -= cache::MAX_AGE.as_secs();
+        cache::store(&cache_path, &stale).unwrap();
+        assert!(find_update(&offline, &current, Some(&cache_path)).is_err());
+
+        let data = archive(NEW);
+        let checksum = verify::sha256_hex(&data);
+        let online = UpdateConfig {
+            release_url: publish("v0.2.0", data, checksum),
+            ..UpdateConfig::default()
+        };
+        let release = find_update(&online, &current, Some(&cache_path)).unwrap();
+        assert_eq!(release.unwrap().tag_name, "v0.2.0");
+        let cached = cache::load(&cache_path).unwrap();
+        assert_eq!(cached.release.unwrap().tag_name, "v0.2.0");
+
+        // Once this binary is at that version, the cached hint is dropped
+        let current = Version::parse("0.2.0").unwrap();
+        assert!(find_update(&offline, &current, Some(&cache_path))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_missing_target_or_signature() {
+        let data = archive(NEW);
+        let checksum = verify::sha256_hex(&data);
+        let mut updater = updater(publish("v99.0.0", data, checksum));
+        let current = Version::parse("0.1.0").unwrap();
+        let release = updater.latest(&current).unwrap().unwrap();
+
+        assert!(updater
+            .download(&release, "riscv64-unknown-linux-gnu")
+            .is_err());
+
+        // Without a key nothing is installed, checksum or not
+        updater.public_key = None;
+        let err = updater.download(&release, TARGET).unwrap_err();
+        assert!(err.to_string().contains("no minisign public key"));
+    }
+
+    #[test]
+    fn test_unsigned_release_is_rejected() {
+        let data = archive(NEW);
+        let checksum = verify::sha256_hex(&data);
+        let updater = updater(publish_signed("v99.0.0", data, checksum, None));
+        let current = Version::parse("0.1.0").unwrap();
+        let release = updater.latest(&current).unwrap().unwrap();
+        let err = updater.download(&release, TARGET).unwrap_err();
+        assert!(err.to_string().contains("no signature"));
+    }
+
+    #[test]
+    fn test_signature_from_another_key_is_rejected() {
+        let data = archive(NEW);
+        let checksum = verify::sha256_hex(&data);
+        let signature = sign(2, &data);
+        let updater = updater(publish_signed("v99.0.0", data, checksum, Some(signature)));
+        let current = Version::parse("0.1.0").unwrap();
+        let release = updater.latest(&current).unwrap().unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let exe = installed_exe(&dir);
+        let err = updater.install(&release, TARGET, &exe).unwrap_err();
+        assert!(err.to_string().contains("signature verification failed"));
+        assert_eq!(std::fs::read(&exe).unwrap(), OLD);
+    }
+}
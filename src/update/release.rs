@@ -0,0 +1,123 @@
+use crate::config::UpdateChannel;
+use anyhow::{anyhow, Result};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+/// One entry of the GitHub "list releases" response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    pub fn version(&self) -> Option<Version> {
+        Version::parse(self.tag_name.strip_prefix('v').unwrap_or(&self.tag_name)).ok()
+    }
+
+    pub fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|a| a.name == name)
+    }
+}
+
+/// The release to update to: the highest version on `channel` that is
+/// newer than `current`, if any.
+pub fn select<'a>(
+    releases: &'a [Release],
+    channel: UpdateChannel,
+    current: &Version,
+) -> Option<&'a Release> {
+    releases
+        .iter()
+        .filter(|r| !r.draft)
+        .filter(|r| channel == UpdateChannel::Prerelease || !r.prerelease)
+        .filter_map(|r| Some((r.version()?, r)))
+        .filter(|(v, _)| v > current)
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, r)| r)
+}
+
+/// Target triple of this build, which names the release archive.
+///
+/// Linux builds stay on the libc flavour they were built for, so a static
+/// musl binary is never replaced by one that needs glibc.
+pub fn target() -> Result<String> {
+    let arch = std::env::consts::ARCH;
+    if !matches!(arch, "x86_64" | "aarch64") {
+        return Err(anyhow!("unsupported architecture: {}", arch));
+    }
+    let os = match std::env::consts::OS {
+        "macos" => "apple-darwin",
+        "linux" if cfg!(target_env = "musl") => "unknown-linux-musl",
+        "linux" => "unknown-linux-gnu",
+        other => return Err(anyhow!("unsupported OS: {}", other)),
+    };
+    Ok(format!("{}-{}", arch, os))
+}
+
+/// Name of the release archive for `target`.
+pub fn archive_name(target: &str) -> String {
+    format!("portmap-{}.tar.gz", target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str, prerelease: bool) -> Release {
+        Release {
+            tag_name: tag.to_string(),
+            draft: false,
+            prerelease,
+            assets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_select_by_channel() {
+        let releases = vec![
+            release("v0.3.0-rc.1", true),
+            release("v0.2.0", false),
+            release("v0.1.5", false),
+            release("nightly", true),
+        ];
+        let current = Version::parse("0.1.2").unwrap();
+
+        let stable = select(&releases, UpdateChannel::Stable, &current).unwrap();
+        assert_eq!(stable.tag_name, "v0.2.0");
+        let pre = select(&releases, UpdateChannel::Prerelease, &current).unwrap();
+        assert_eq!(pre.tag_name, "v0.3.0-rc.1");
+
+        let current = Version::parse("0.2.0").unwrap();
+        assert!(select(&releases, UpdateChannel::Stable, &current).is_none());
+    }
+
+    #[test]
+    fn test_drafts_ignored() {
+        let mut draft = release("v9.0.0", false);
+        draft.draft = true;
+        let current = Version::parse("0.1.0").unwrap();
+        assert!(select(&[draft], UpdateChannel::Prerelease, &current).is_none());
+    }
+
+    #[test]
+    fn test_target_names_archive() {
+        let target = target().unwrap();
+        assert!(target.ends_with("-apple-darwin") || target.contains("-unknown-linux-"));
+        assert_eq!(
+            archive_name("x86_64-unknown-linux-musl"),
+            "portmap-x86_64-unknown-linux-musl.tar.gz"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{anyhow, bail, Result};
+use minisign_verify::{PublicKey, Signature};
+use sha2::{Digest, Sha256};
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Check `data` against a published `.sha256` file.
+///
+/// Accepts both a bare digest and `sha256sum` output (`<digest>  <name>`).
+pub fn verify_sha256(data: &[u8], checksum_file: &str, name: &str) -> Result<()> {
+    let expected = checksum_file
+        .lines()
+        .find_map(|line| {
+            let mut parts = line.split_whitespace();
+            let digest = parts.next()?;
+            match parts.next() {
+                None => Some(digest),
+                Some(file) if file.trim_start_matches('*') == name => Some(digest),
+                Some(_) => None,
+            }
+        })
+        .ok_or_else(|| anyhow!("no checksum for {} in the published .sha256", name))?;
+
+    let actual = sha256_hex(data);
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!(
+            "checksum mismatch for {}: expected {}, got {}",
+            name,
+            expected,
+            actual
+        );
+    }
+    Ok(())
+}
+
+/// Check `data` against a minisign signature made with `public_key`.
+pub fn verify_minisign(data: &[u8], signature: &str, public_key: &str) -> Result<()> {
+    let key = PublicKey::from_base64(public_key.trim())
+        .map_err(|e| anyhow!("invalid minisign public key: {}", e))?;
+    let signature =
+        Signature::decode(signature).map_err(|e| anyhow!("invalid minisign signature: {}", e))?;
+    key.verify(data, &signature, true)
+        .map_err(|e| anyhow!("signature verification failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vector from the minisign-verify crate
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==";
+
+    #[test]
+    fn test_sha256_formats() {
+        let digest = sha256_hex(b"test");
+        assert_eq!(
+            digest,
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+        assert!(verify_sha256(b"test", &digest, "a.tar.gz").is_ok());
+        assert!(verify_sha256(b"test", &format!("{}  a.tar.gz\n", digest), "a.tar.gz").is_ok());
+        assert!(verify_sha256(b"test", &format!("{} *a.tar.gz\n", digest), "a.tar.gz").is_ok());
+    }
+
+    #[test]
+    fn test_sha256_mismatch() {
+        let digest = sha256_hex(b"test");
+        assert!(verify_sha256(b"tampered", &digest, "a.tar.gz").is_err());
+        assert!(verify_sha256(b"test", &format!("{}  b.tar.gz\n", digest), "a.tar.gz").is_err());
+        assert!(verify_sha256(b"test", "", "a.tar.gz").is_err());
+    }
+
+    #[test]
+    fn test_minisign() {
+        assert!(verify_minisign(b"test", SIGNATURE, PUBLIC_KEY).is_ok());
+        assert!(verify_minisign(b"Test", SIGNATURE, PUBLIC_KEY).is_err());
+        assert!(verify_minisign(b"test", "garbage", PUBLIC_KEY).is_err());
+    }
+}